@@ -0,0 +1,653 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use once_cell::race::OnceBox;
+
+use crate::board::{step, BitBoard, BoardState, Square, BISHOP_DIRECTIONS, KING_DELTAS, KNIGHT_DELTAS, ROOK_DIRECTIONS, TOTAL_SQUARES};
+use crate::moves::ChessMove;
+use crate::pieces::{Piece, PieceColour, PieceKind};
+
+/// Exchange value of a king: far more than anything it could capture, so it never
+/// recaptures onto a square the opponent still covers.
+const SEE_KING_VALUE: i32 = 20_000;
+
+impl BoardState {
+    /// Check if a square is attacked by any piece of the given colour.
+    pub fn is_square_attacked(&self, square: usize, by: PieceColour) -> bool {
+        self.attackers_to(square, by).0 != 0
+    }
+
+    /// Find all pieces of the given colour that attack a square.
+    pub fn attackers_to(&self, square: usize, by: PieceColour) -> BitBoard {
+        let (pawns, knights, bishops, rooks, queens, king) = match by {
+            PieceColour::White => (
+                self.white_pawns,
+                self.white_knights,
+                self.white_bishops,
+                self.white_rooks,
+                self.white_queens,
+                self.white_king,
+            ),
+            PieceColour::Black => (
+                self.black_pawns,
+                self.black_knights,
+                self.black_bishops,
+                self.black_rooks,
+                self.black_queens,
+                self.black_king,
+            ),
+        };
+
+        // Pawns attack diagonally forwards, so look diagonally backwards from the square
+        let pawn_deltas = match by {
+            PieceColour::White => [(-1, -1), (-1, 1)],
+            PieceColour::Black => [(1, -1), (1, 1)],
+        };
+        let mut attackers = pieces_at_deltas(square, &pawn_deltas, &pawns);
+        attackers |= pieces_at_deltas(square, &KNIGHT_DELTAS, &knights).0;
+        attackers |= pieces_at_deltas(square, &KING_DELTAS, &king).0;
+
+        let diagonal = BitBoard(bishops.0 | queens.0);
+        let straight = BitBoard(rooks.0 | queens.0);
+        for (directions, sliders) in [(&BISHOP_DIRECTIONS, diagonal), (&ROOK_DIRECTIONS, straight)] {
+            for &direction in directions {
+                if let Some(target) = self.first_piece_along(square, direction) {
+                    if sliders.is_set(target) {
+                        attackers.set(target);
+                    }
+                }
+            }
+        }
+
+        attackers
+    }
+
+    /// Squares attacked by the piece on the square, including squares held by either side.
+    /// Sliders stop at the first piece in each direction; an empty square attacks nothing.
+    pub fn attacks_from(&self, square: usize) -> BitBoard {
+        let Some(piece) = self.piece_at(square) else {
+            return BitBoard::empty();
+        };
+
+        match piece.kind {
+            PieceKind::Pawn => {
+                let forward = match piece.colour {
+                    PieceColour::White => 1,
+                    PieceColour::Black => -1,
+                };
+                squares_at_deltas(square, &[(forward, -1), (forward, 1)])
+            }
+            PieceKind::Knight => squares_at_deltas(square, &KNIGHT_DELTAS),
+            PieceKind::King => squares_at_deltas(square, &KING_DELTAS),
+            PieceKind::Bishop => self.ray_attacks(square, &BISHOP_DIRECTIONS),
+            PieceKind::Rook => self.ray_attacks(square, &ROOK_DIRECTIONS),
+            PieceKind::Queen => self.ray_attacks(square, &KING_DELTAS),
+        }
+    }
+
+    /// Every square attacked by at least one piece of the given colour.
+    pub fn attacked_squares(&self, by: PieceColour) -> BitBoard {
+        let own = match by {
+            PieceColour::White => self.all_white,
+            PieceColour::Black => self.all_black,
+        };
+
+        let mut attacked = BitBoard::empty();
+        for square in own.iter() {
+            attacked |= self.attacks_from(square.index()).0;
+        }
+        attacked
+    }
+
+    /// Count the squares of a king's zone, the king's own square and its neighbours, that the
+    /// enemy attacks. A rough measure of how exposed the king is.
+    pub fn attack_count_near_king(&self, colour: PieceColour) -> u32 {
+        let king = self.king_square(colour);
+        let mut zone = squares_at_deltas(king, &KING_DELTAS);
+        zone.set(king);
+        (self.attacked_squares(colour.opposite()) & zone.0).count()
+    }
+
+    /// Check if the piece on a square is protected by another piece of its own colour.
+    pub fn is_defended(&self, square: usize) -> bool {
+        self.piece_at(square)
+            .is_some_and(|piece| self.is_square_attacked(square, piece.colour))
+    }
+
+    /// Find the pieces of the given colour that are pinned to their own king by enemy sliders.
+    pub fn pinned_pieces(&self, colour: PieceColour) -> BitBoard {
+        let own = match colour {
+            PieceColour::White => self.all_white,
+            PieceColour::Black => self.all_black,
+        };
+        self.xray_blockers(self.king_square(colour), own, colour.opposite())
+    }
+
+    /// Find the pieces of the given colour that would uncover a check on the enemy king if they moved.
+    pub fn discovered_check_candidates(&self, by: PieceColour) -> BitBoard {
+        let own = match by {
+            PieceColour::White => self.all_white,
+            PieceColour::Black => self.all_black,
+        };
+        self.xray_blockers(self.king_square(by.opposite()), own, by)
+    }
+
+    /// Find the square of the king of the given colour.
+    pub fn king_square(&self, colour: PieceColour) -> usize {
+        let king = match colour {
+            PieceColour::White => self.white_king,
+            PieceColour::Black => self.black_king,
+        };
+        king.0.trailing_zeros() as usize
+    }
+
+    /// Find the enemy pieces giving check to the king of the given colour. Two of them
+    /// make a double check, which only a king move can answer.
+    pub fn checkers(&self, colour: PieceColour) -> BitBoard {
+        self.attackers_to(self.king_square(colour), colour.opposite())
+    }
+
+    /// Check if the king of the given colour is attacked.
+    pub fn is_in_check(&self, colour: PieceColour) -> bool {
+        self.is_square_attacked(self.king_square(colour), colour.opposite())
+    }
+
+    /// Check if the side to move is in check.
+    pub fn side_to_move_in_check(&self) -> bool {
+        self.is_in_check(self.to_move)
+    }
+
+    /// Static exchange evaluation of a capture: the material the side making it wins (or
+    /// loses, when negative) if both sides keep recapturing on the target square with their
+    /// cheapest piece for as long as that pays.
+    pub fn see(&self, chess_move: &ChessMove) -> i32 {
+        let Some(mover) = self.piece_at(chess_move.from) else {
+            return 0;
+        };
+
+        let mut board = self.clone();
+        let captured = match self.piece_at(chess_move.to) {
+            Some(victim) => see_value(victim.kind),
+            None if mover.kind == PieceKind::Pawn && chess_move.from % 8 != chess_move.to % 8 => {
+                // En passant, the pawn is taken from behind the target square
+                let victim_square = (chess_move.to as isize - mover.colour.pawn_push_offset()) as usize;
+                board.remove_piece(victim_square);
+                see_value(PieceKind::Pawn)
+            }
+            None => 0,
+        };
+
+        board.remove_piece(chess_move.from);
+        board.add_piece(chess_move.to, Piece {
+            kind: chess_move.promotion.unwrap_or(mover.kind),
+            colour: mover.colour,
+        });
+        captured - board.exchange_on(chess_move.to, mover.colour.opposite())
+    }
+
+    /// The most `side` can win by capturing on the square with its cheapest attacker and
+    /// carrying on the exchange, or zero if it is better to leave the piece alone.
+    fn exchange_on(&self, square: usize, side: PieceColour) -> i32 {
+        let Some(victim) = self.piece_at(square) else {
+            return 0;
+        };
+        let Some(attacker) = self
+            .attackers_to(square, side)
+            .iter()
+            .map(Square::index)
+            .filter_map(|attacker_square| self.piece_at(attacker_square).map(|piece| (attacker_square, piece)))
+            .min_by_key(|&(_, piece)| see_value(piece.kind))
+        else {
+            return 0;
+        };
+
+        // Capturing removes the attacker from its square, which can uncover an x-ray behind it
+        let mut board = self.clone();
+        board.remove_piece(attacker.0);
+        board.add_piece(square, attacker.1);
+        (see_value(victim.kind) - board.exchange_on(square, side.opposite())).max(0)
+    }
+
+    /// Look along every line from the king and collect the single `candidates` piece
+    /// standing between it and a slider of `sliders_colour` moving along that line.
+    fn xray_blockers(&self, king: usize, candidates: BitBoard, sliders_colour: PieceColour) -> BitBoard {
+        let (bishops, rooks, queens) = match sliders_colour {
+            PieceColour::White => (self.white_bishops, self.white_rooks, self.white_queens),
+            PieceColour::Black => (self.black_bishops, self.black_rooks, self.black_queens),
+        };
+        let diagonal = BitBoard(bishops.0 | queens.0);
+        let straight = BitBoard(rooks.0 | queens.0);
+
+        let mut blockers = BitBoard::empty();
+        for (directions, sliders) in [(&BISHOP_DIRECTIONS, diagonal), (&ROOK_DIRECTIONS, straight)] {
+            for &direction in directions {
+                let Some(blocker) = self.first_piece_along(king, direction) else {
+                    continue;
+                };
+                if !candidates.is_set(blocker) {
+                    continue;
+                }
+                if self
+                    .first_piece_along(blocker, direction)
+                    .is_some_and(|behind| sliders.is_set(behind))
+                {
+                    blockers.set(blocker);
+                }
+            }
+        }
+        blockers
+    }
+
+    /// Squares a slider reaches in the given directions, up to and including the first piece.
+    fn ray_attacks(&self, square: usize, directions: &[(i8, i8)]) -> BitBoard {
+        let mut attacks = BitBoard::empty();
+        for &(rank_delta, file_delta) in directions {
+            let mut target = step(square, rank_delta, file_delta);
+            while let Some(target_square) = target {
+                attacks.set(target_square);
+                if self.all_pieces.is_set(target_square) {
+                    break;
+                }
+                target = step(target_square, rank_delta, file_delta);
+            }
+        }
+        attacks
+    }
+
+    /// Walk from a square in a sliding direction and return the first occupied square, if any.
+    fn first_piece_along(&self, square: usize, (rank_delta, file_delta): (i8, i8)) -> Option<usize> {
+        let mut target = step(square, rank_delta, file_delta);
+        while let Some(target_square) = target {
+            if self.all_pieces.is_set(target_square) {
+                return Some(target_square);
+            }
+            target = step(target_square, rank_delta, file_delta);
+        }
+        None
+    }
+}
+
+/// Piece value for exchanges, where a king is worth more than any other piece.
+fn see_value(kind: PieceKind) -> i32 {
+    match kind {
+        PieceKind::King => SEE_KING_VALUE,
+        kind => kind.value(),
+    }
+}
+
+/// Every square attacked by a set of pawns of the given colour, one step diagonally forward.
+pub fn pawn_attacks(pawns: BitBoard, colour: PieceColour) -> BitBoard {
+    let forward = colour.pawn_push_offset() as i8;
+    let mut attacks = pawns.shift(forward - 1);
+    attacks |= pawns.shift(forward + 1).0;
+    attacks
+}
+
+/// The squares strictly between two squares on the same rank, file or diagonal, such as the
+/// squares a piece could block a check or pin on. Empty when the squares aren't aligned or
+/// are next to each other.
+pub fn between(a: usize, b: usize) -> BitBoard {
+    let mut squares = BitBoard::empty();
+    let Some((rank_delta, file_delta)) = direction_towards(a, b) else {
+        return squares;
+    };
+
+    let mut square = a;
+    while let Some(next) = step(square, rank_delta, file_delta).filter(|&next| next != b) {
+        squares.set(next);
+        square = next;
+    }
+    squares
+}
+
+/// The whole rank, file or diagonal through two aligned squares, edge to edge and including
+/// both of them, such as the ray a pinned piece may still move along. Empty when the squares
+/// aren't aligned or are the same square.
+pub fn line(a: usize, b: usize) -> BitBoard {
+    static LINES: OnceBox<Vec<[BitBoard; TOTAL_SQUARES]>> = OnceBox::new();
+    let lines = LINES.get_or_init(|| {
+        let rows = (0..TOTAL_SQUARES).map(|a| {
+            let mut row = [BitBoard::empty(); TOTAL_SQUARES];
+            for (b, entry) in row.iter_mut().enumerate() {
+                if let Some((rank_delta, file_delta)) = direction_towards(a, b) {
+                    *entry = ray_through(a, rank_delta, file_delta);
+                }
+            }
+            row
+        });
+        Box::new(rows.collect())
+    });
+    lines[a][b]
+}
+
+/// The square itself and every square from it to both edges along one direction.
+fn ray_through(square: usize, rank_delta: i8, file_delta: i8) -> BitBoard {
+    let mut ray = BitBoard::empty();
+    ray.set(square);
+    for (rank_delta, file_delta) in [(rank_delta, file_delta), (-rank_delta, -file_delta)] {
+        let mut current = square;
+        while let Some(next) = step(current, rank_delta, file_delta) {
+            ray.set(next);
+            current = next;
+        }
+    }
+    ray
+}
+
+/// The single (rank, file) step that walks from `a` to `b` along a rank, file or diagonal,
+/// or `None` if no such line joins them.
+fn direction_towards(a: usize, b: usize) -> Option<(i8, i8)> {
+    let rank_distance = (b / 8) as i8 - (a / 8) as i8;
+    let file_distance = (b % 8) as i8 - (a % 8) as i8;
+    let aligned = rank_distance == 0 || file_distance == 0 || rank_distance.abs() == file_distance.abs();
+    if a == b || !aligned {
+        return None;
+    }
+    Some((rank_distance.signum(), file_distance.signum()))
+}
+
+/// Collect the squares a single step away from the square, for each step that stays on the board.
+fn squares_at_deltas(square: usize, deltas: &[(i8, i8)]) -> BitBoard {
+    let mut found = BitBoard::empty();
+    for target in deltas
+        .iter()
+        .filter_map(|&(rank_delta, file_delta)| step(square, rank_delta, file_delta))
+    {
+        found.set(target);
+    }
+    found
+}
+
+/// Collect the given pieces that sit a single step away from the square.
+fn pieces_at_deltas(square: usize, deltas: &[(i8, i8)], pieces: &BitBoard) -> BitBoard {
+    let mut found = BitBoard::empty();
+    for target in deltas
+        .iter()
+        .filter_map(|&(rank_delta, file_delta)| step(square, rank_delta, file_delta))
+    {
+        if pieces.is_set(target) {
+            found.set(target);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{KNIGHT_VALUE, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE};
+    use crate::fen::{parse_square, square_name};
+
+    #[test]
+    fn test_is_square_attacked_initial_board() {
+        let board = BoardState::new();
+
+        // Third rank is covered by white pawns and knights, the fourth is not
+        assert!(board.is_square_attacked(16, PieceColour::White)); // a3
+        assert!(board.is_square_attacked(21, PieceColour::White)); // f3
+        assert!(!board.is_square_attacked(28, PieceColour::White)); // e4
+        assert!(board.is_square_attacked(44, PieceColour::Black)); // e6
+        assert!(!board.is_square_attacked(44, PieceColour::White));
+    }
+
+    /// Names of the squares attacked by the given colour, in square order, with one piece of
+    /// that colour on the board and any number of enemy pawns in its way.
+    fn attacked_by(kind: PieceKind, colour: PieceColour, square: &str, blockers: &[&str]) -> Vec<String> {
+        let mut board = BoardState::empty();
+        board.add_piece(parse_square(square).unwrap(), Piece { kind, colour });
+        for blocker in blockers {
+            board.add_piece(parse_square(blocker).unwrap(), Piece { kind: PieceKind::Pawn, colour: colour.opposite() });
+        }
+        (0..TOTAL_SQUARES)
+            .filter(|&target| board.is_square_attacked(target, colour))
+            .map(square_name)
+            .collect()
+    }
+
+    #[test]
+    fn test_is_square_attacked_by_each_piece() {
+        use PieceColour::{Black, White};
+        use PieceKind::{Bishop, King, Knight, Pawn, Queen, Rook};
+
+        // Pawns take diagonally forwards only, and only inwards from the edge files
+        assert_eq!(attacked_by(Pawn, White, "e4", &[]), ["d5", "f5"]);
+        assert_eq!(attacked_by(Pawn, White, "a2", &[]), ["b3"]);
+        assert_eq!(attacked_by(Pawn, White, "h7", &[]), ["g8"]);
+        assert_eq!(attacked_by(Pawn, Black, "e5", &[]), ["d4", "f4"]);
+        assert_eq!(attacked_by(Pawn, Black, "h2", &[]), ["g1"]);
+        assert_eq!(attacked_by(Pawn, Black, "a7", &[]), ["b6"]);
+
+        // Knights from the centre and from the corners
+        assert_eq!(attacked_by(Knight, White, "d4", &[]), ["c2", "e2", "b3", "f3", "b5", "f5", "c6", "e6"]);
+        assert_eq!(attacked_by(Knight, Black, "a1", &[]), ["c2", "b3"]);
+        assert_eq!(attacked_by(Knight, White, "h8", &[]), ["g6", "f7"]);
+        assert_eq!(attacked_by(Knight, Black, "g2", &[]), ["e1", "e3", "f4", "h4"]);
+
+        // Bishops along all four diagonals, up to and including the first piece
+        assert_eq!(
+            attacked_by(Bishop, White, "d4", &[]),
+            ["a1", "g1", "b2", "f2", "c3", "e3", "c5", "e5", "b6", "f6", "a7", "g7", "h8"]
+        );
+        assert_eq!(attacked_by(Bishop, Black, "h1", &[]), ["g2", "f3", "e4", "d5", "c6", "b7", "a8"]);
+        assert_eq!(attacked_by(Bishop, White, "c1", &["e3", "a3"]), ["b2", "d2", "a3", "e3"]);
+
+        // Rooks along ranks and files, from the edge and stopped by the first piece
+        assert_eq!(
+            attacked_by(Rook, Black, "a8", &[]),
+            ["a1", "a2", "a3", "a4", "a5", "a6", "a7", "b8", "c8", "d8", "e8", "f8", "g8", "h8"]
+        );
+        assert_eq!(attacked_by(Rook, White, "e4", &["e6", "e2", "c4", "g4"]), [
+            "e2", "e3", "c4", "d4", "f4", "g4", "e5", "e6"
+        ]);
+
+        // Queens combine both, in all eight directions
+        let queen = attacked_by(Queen, White, "d4", &[]);
+        assert_eq!(queen.len(), 27);
+        for square in ["a1", "d1", "g1", "a4", "h4", "a7", "d8", "h8"] {
+            assert!(queen.iter().any(|name| name == square), "{}", square);
+        }
+        assert_eq!(attacked_by(Queen, Black, "h8", &["f6", "h5", "e8"]), [
+            "h5", "f6", "h6", "g7", "h7", "e8", "f8", "g8"
+        ]);
+
+        // Kings one step each way, fewer on edges and in corners
+        assert_eq!(attacked_by(King, White, "e4", &[]), ["d3", "e3", "f3", "d4", "f4", "d5", "e5", "f5"]);
+        assert_eq!(attacked_by(King, Black, "e1", &[]), ["d1", "f1", "d2", "e2", "f2"]);
+        assert_eq!(attacked_by(King, White, "a8", &[]), ["a7", "b7", "b8"]);
+        assert_eq!(attacked_by(King, Black, "h1", &[]), ["g1", "g2", "h2"]);
+
+        // A piece of the same colour blocks too, and attacks belong to their own colour only
+        let mut board = BoardState::empty();
+        board.add_piece(28, Piece { kind: Rook, colour: White }); // e4
+        board.add_piece(44, Piece { kind: Pawn, colour: White }); // e6
+        assert!(board.is_square_attacked(44, White)); // e6
+        assert!(!board.is_square_attacked(52, White)); // e7
+        assert!(!board.is_square_attacked(36, Black)); // e5
+    }
+
+    #[test]
+    fn test_attacks_do_not_wrap_around_the_board() {
+        let mut board = BoardState::empty();
+        board.add_piece(7, Piece { kind: PieceKind::Rook, colour: PieceColour::Black }); // h1
+
+        // h1 rook attacks along the first rank and h-file, but not a2
+        assert!(board.is_square_attacked(0, PieceColour::Black)); // a1
+        assert!(board.is_square_attacked(63, PieceColour::Black)); // h8
+        assert!(!board.is_square_attacked(8, PieceColour::Black)); // a2
+    }
+
+    #[test]
+    fn test_attackers_to() {
+        // The d4 pawn and e1 rook hit e5 for white, the c6 knight for black
+        let board = BoardState::from_fen("k7/8/2n5/8/3P4/8/8/4RK2 w - - 0 1").unwrap();
+
+        assert_eq!(board.attackers_to(36, PieceColour::White), BitBoard((1 << 27) | (1 << 4)));
+        assert_eq!(board.attackers_to(36, PieceColour::Black), BitBoard(1 << 42));
+    }
+
+    #[test]
+    fn test_attacks_from() {
+        let board = BoardState::new();
+        // The b1 knight covers a3, c3 and its own d2 pawn
+        assert_eq!(board.attacks_from(1), BitBoard((1 << 16) | (1 << 18) | (1 << 11)));
+        // The a1 rook is boxed in by its own pieces on a2 and b1
+        assert_eq!(board.attacks_from(0), BitBoard((1 << 8) | (1 << 1)));
+        // Black pawns attack downwards
+        assert_eq!(board.attacks_from(52), BitBoard((1 << 43) | (1 << 45)));
+        assert_eq!(board.attacks_from(28), BitBoard::empty());
+
+        // Every attacker found by attackers_to sees the square from its own side too
+        let board = BoardState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for square in 0..64 {
+            for colour in [PieceColour::White, PieceColour::Black] {
+                for attacker in board.attackers_to(square, colour).iter() {
+                    assert!(board.attacks_from(attacker.index()).is_set(square));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pawn_attacks() {
+        // a2 and e2 for white, h7 for black; pawns on the edge only attack inwards
+        let white = BitBoard(1 << 8 | 1 << 12);
+        assert_eq!(pawn_attacks(white, PieceColour::White), BitBoard(1 << 17 | 1 << 19 | 1 << 21));
+        assert_eq!(pawn_attacks(BitBoard(1 << 55), PieceColour::Black), BitBoard(1 << 46));
+    }
+
+    #[test]
+    fn test_between() {
+        // b1 and c1 lie between a1 and d1, in either order
+        assert_eq!(between(0, 3), BitBoard((1 << 1) | (1 << 2)));
+        assert_eq!(between(3, 0), between(0, 3));
+        // Along the a-file and the a1-h8 diagonal
+        assert_eq!(between(0, 24), BitBoard((1 << 8) | (1 << 16)));
+        assert_eq!(between(63, 27), BitBoard((1 << 36) | (1 << 45) | (1 << 54)));
+
+        // Neighbours, squares off any line and a square with itself have nothing between
+        assert_eq!(between(0, 9), BitBoard::empty());
+        assert_eq!(between(0, 10), BitBoard::empty());
+        assert_eq!(between(7, 8), BitBoard::empty());
+        assert_eq!(between(5, 5), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_line() {
+        // The long diagonal through a1 and b2
+        assert_eq!(line(0, 9), BitBoard(0x8040_2010_0804_0201));
+        assert_eq!(line(63, 27), line(0, 9));
+        // The whole second rank through c2 and f2, and the whole e-file
+        assert_eq!(line(10, 13), BitBoard(0xFF << 8));
+        assert_eq!(line(60, 4), BitBoard(0x1010_1010_1010_1010));
+
+        assert_eq!(line(0, 10), BitBoard::empty());
+        assert_eq!(line(12, 12), BitBoard::empty());
+
+        // What lies between two squares is always on their line
+        for (a, b) in [(0, 63), (5, 61), (18, 50), (7, 56)] {
+            assert_eq!(between(a, b).0 & !line(a, b).0, 0);
+        }
+    }
+
+    #[test]
+    fn test_attack_count_near_king() {
+        // Nothing of Black's comes near the castled white king
+        let quiet = BoardState::from_fen("r5k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(quiet.attack_count_near_king(PieceColour::White), 0);
+
+        // The queen, rook and knight all bear down on it
+        let attacked = BoardState::from_fen("6k1/5ppp/8/8/7q/5n2/5PPP/4r1K1 w - - 0 1").unwrap();
+        assert!(attacked.attack_count_near_king(PieceColour::White) > 3);
+        assert_eq!(attacked.attack_count_near_king(PieceColour::Black), 0);
+    }
+
+    #[test]
+    fn test_is_defended() {
+        // The d4 pawn guards e5's knight, nothing guards the a5 knight
+        let board = BoardState::from_fen("4k3/8/8/N3N3/3P4/8/8/4K3 w - - 0 1").unwrap();
+
+        assert!(board.is_defended(36)); // e5
+        assert!(!board.is_defended(32)); // a5
+        assert!(!board.is_defended(20)); // empty e3
+    }
+
+    #[test]
+    fn test_pinned_pieces() {
+        // The e4 knight is pinned by the e8 rook, the d2 bishop is free to move
+        let board = BoardState::from_fen("k3r3/8/8/8/4N3/8/3B4/4K3 w - - 0 1").unwrap();
+
+        let pinned = board.pinned_pieces(PieceColour::White);
+        assert_eq!(pinned, BitBoard(1 << 28)); // e4
+        assert_eq!(board.pinned_pieces(PieceColour::Black), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_two_blockers_are_not_pinned() {
+        // With both the knight and the pawn on the file, neither is pinned
+        let board = BoardState::from_fen("k3r3/8/8/4P3/4N3/8/8/4K3 w - - 0 1").unwrap();
+
+        assert_eq!(board.pinned_pieces(PieceColour::White), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_discovered_check_candidates() {
+        // The d4 knight stands between the b2 bishop and the black king on f6
+        let board = BoardState::from_fen("8/8/5k2/8/3N4/8/1B6/4K3 w - - 0 1").unwrap();
+
+        assert_eq!(board.discovered_check_candidates(PieceColour::White), BitBoard(1 << 27)); // d4
+        assert_eq!(board.discovered_check_candidates(PieceColour::Black), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_is_in_check() {
+        let board = BoardState::from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+
+        assert!(board.is_in_check(PieceColour::Black));
+        assert!(!board.is_in_check(PieceColour::White));
+    }
+
+    #[test]
+    fn test_checkers() {
+        // Double check from the d6 knight and the e1 rook
+        let board = BoardState::from_fen("4k3/8/3N4/8/8/8/8/4R2K b - - 0 1").unwrap();
+        assert_eq!(board.checkers(PieceColour::Black), BitBoard((1 << 43) | (1 << 4)));
+        assert_eq!(board.checkers(PieceColour::White), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_side_to_move_in_check() {
+        assert!(!BoardState::new().side_to_move_in_check());
+
+        // Scholar's mate leaves black in check
+        let board =
+            BoardState::from_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4").unwrap();
+        assert!(board.side_to_move_in_check());
+    }
+
+    #[test]
+    fn test_see() {
+        // The e5 pawn is guarded by d6, the b5 rook is loose, and the d1 rook backs up the d3 one
+        let board = BoardState::from_fen("4k3/8/3p4/1r2p3/8/2NR4/8/3RK3 w - - 0 1").unwrap();
+
+        // Nxb5 wins a rook outright
+        assert_eq!(board.see(&ChessMove { from: 18, to: 33, promotion: None }), ROOK_VALUE);
+        // Nxe5 dxe5 loses the knight for a pawn
+        assert_eq!(board.see(&ChessMove { from: 18, to: 36, promotion: None }), PAWN_VALUE - KNIGHT_VALUE);
+        // Rxd6 wins a pawn; nothing recaptures
+        assert_eq!(board.see(&ChessMove { from: 19, to: 43, promotion: None }), PAWN_VALUE);
+    }
+
+    #[test]
+    fn test_see_xray_recapture() {
+        // Rxd5 Rxd5 Qxd5: the d1 queen recaptures through the d3 rook's square
+        let board = BoardState::from_fen("3rk3/8/8/3p4/8/3R4/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(board.see(&ChessMove { from: 19, to: 35, promotion: None }), PAWN_VALUE);
+
+        // With the queen in front, taking first throws the queen away
+        let board = BoardState::from_fen("3rk3/8/8/3p4/8/3Q4/8/3RK3 w - - 0 1").unwrap();
+        assert_eq!(
+            board.see(&ChessMove { from: 19, to: 35, promotion: None }),
+            PAWN_VALUE - QUEEN_VALUE + ROOK_VALUE
+        );
+    }
+}
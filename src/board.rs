@@ -1,33 +1,116 @@
 use crate::pieces::{Piece, PieceColour, PieceKind};
-use crate::moves::ChessMove;
+use crate::moves::{ChessMove, MoveError, MoveUndo};
+use crate::fen::square_name;
 use crate::zorbist::ZobristHashing;
-use std::ops::{BitAnd, BitAndAssign, BitOrAssign};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{BitAnd, BitAndAssign, BitOrAssign, BitXorAssign};
 
 pub const BOARD_SIZE: usize = 8;
 pub const TOTAL_SQUARES: usize = 64;
+/// Every square on the a-file; shift left by the file index for the others.
+pub const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+/// (rank, file) steps for a knight's jumps.
+pub const KNIGHT_DELTAS: [(i8, i8); 8] = [(2, 1), (2, -1), (1, 2), (1, -2), (-1, 2), (-1, -2), (-2, 1), (-2, -1)];
+/// (rank, file) steps for a king, which are also the queen's sliding directions.
+pub const KING_DELTAS: [(i8, i8); 8] = [(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)];
+pub const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+pub const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Move from a square by a number of ranks and files, returning `None` if that leaves the board.
+pub fn step(square: usize, rank_delta: i8, file_delta: i8) -> Option<usize> {
+    let rank = (square / BOARD_SIZE) as i8 + rank_delta;
+    let file = (square % BOARD_SIZE) as i8 + file_delta;
+    if (0..BOARD_SIZE as i8).contains(&rank) && (0..BOARD_SIZE as i8).contains(&file) {
+        Some(rank as usize * BOARD_SIZE + file as usize)
+    } else {
+        None
+    }
+}
 
 /// Represents a bitboard as a 64-bit integer.
+///
+/// Squares are numbered 0 (a1) to 63 (h8). The square methods expect an index in that range;
+/// use `try_set` when the square comes from outside and might not be.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BitBoard(pub u64);
 
+/// A square index outside 0..64.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct OutOfRange(pub usize);
+
 impl BitBoard {
     pub fn empty() -> Self {
         BitBoard(0)
     }
 
     pub fn set(&mut self, square: usize) {
-        self.0 |= 1 << square;
+        self.0 |= Self::bit(square);
         tracing::debug!("Set bitboard: {:064b} (square: {})", self.0, square);
     }
 
+    /// Set a square that may be off the board, reporting it instead of setting anything.
+    pub fn try_set(&mut self, square: usize) -> Result<(), OutOfRange> {
+        if square >= TOTAL_SQUARES {
+            return Err(OutOfRange(square));
+        }
+        self.set(square);
+        Ok(())
+    }
+
     pub fn clear(&mut self, square: usize) {
-        self.0 &= !(1 << square);
+        self.0 &= !Self::bit(square);
     }
 
     pub fn is_set(&self, square: usize) -> bool {
-        self.0 & (1 << square) != 0
+        self.0 & Self::bit(square) != 0
+    }
+
+    /// Flip a square: set it if clear, clear it if set.
+    pub fn toggle(&mut self, square: usize) {
+        self.0 ^= Self::bit(square);
+    }
+
+    /// Move every set square by `delta` (+8 is a rank up, +1 a file right), dropping squares
+    /// that would leave the board or wrap round onto the other edge. The file step is taken
+    /// as the one nearest zero, so +6 is a rank up and two files left, like a knight's jump.
+    pub fn shift(self, delta: i8) -> BitBoard {
+        let delta = i32::from(delta);
+        let ranks = (delta + 4).div_euclid(BOARD_SIZE as i32);
+        let files = delta - ranks * BOARD_SIZE as i32;
+
+        // Drop the files that would be pushed over the edge before shifting
+        let mut keep = self.0;
+        for file in 0..BOARD_SIZE as i32 {
+            if !(0..BOARD_SIZE as i32).contains(&(file + files)) {
+                keep &= !(FILE_A << file);
+            }
+        }
+
+        let shifted = if delta >= 0 {
+            keep.checked_shl(delta as u32)
+        } else {
+            keep.checked_shr(delta.unsigned_abs())
+        };
+        BitBoard(shifted.unwrap_or(0))
+    }
+
+    /// The single bit for a square. Release builds don't check the range, where a shift past
+    /// 63 would silently wrap onto another square.
+    fn bit(square: usize) -> u64 {
+        debug_assert!(square < TOTAL_SQUARES, "Square {} is off the board", square);
+        1 << square
+    }
+
+    /// Returns the number of set bits.
+    pub fn count(&self) -> u32 {
+        self.0.count_ones()
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn print(&self) {
         for rank in (0..BOARD_SIZE).rev() {
             for file in 0..BOARD_SIZE {
@@ -45,6 +128,40 @@ impl BitBoard {
 
 }
 
+impl From<u64> for BitBoard {
+    fn from(bits: u64) -> Self {
+        BitBoard(bits)
+    }
+}
+
+impl From<BitBoard> for u64 {
+    fn from(bitboard: BitBoard) -> Self {
+        bitboard.0
+    }
+}
+
+/// A square on the board, from a1 (0) to h8 (63).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(index: usize) -> Self {
+        debug_assert!(index < TOTAL_SQUARES, "Square index {} is off the board", index);
+        Square(index as u8)
+    }
+
+    /// The square as a raw index, for indexing bitboards and tables.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", square_name(self.index()))
+    }
+}
+
 impl BitAndAssign<u64> for BitBoard {
     fn bitand_assign(&mut self, rhs: u64) {
         self.0 &= rhs;
@@ -65,8 +182,14 @@ impl BitOrAssign<u64> for BitBoard {
     }
 }
 
+impl BitXorAssign<u64> for BitBoard {
+    fn bitxor_assign(&mut self, rhs: u64) {
+        self.0 ^= rhs;
+    }
+}
+
 /// Represents the entire chessboard using bitboards.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BoardState {
     pub white_pawns: BitBoard,
     pub black_pawns: BitBoard,
@@ -86,10 +209,32 @@ pub struct BoardState {
     pub to_move: PieceColour,
     pub castling_rights: [bool; 4],
     pub en_passant_square: Option<usize>,
+    pub half_move_clock: u16, // Plies since the last pawn move or capture
+    zobrist: u64, // Hash of the position, kept up to date by every move
+    undo_stack: Vec<MoveUndo>, // Moves played with `apply_move`, for `undo_last`
 }
 
 impl BoardState {
     pub fn new() -> Self {
+        let mut board = BoardState::empty();
+        board.reset();
+        board
+    }
+
+    /// Put the board back to the start position in place. The undo stack is emptied but keeps
+    /// its allocation.
+    pub fn reset(&mut self) {
+        let mut undo_stack = core::mem::take(&mut self.undo_stack);
+        undo_stack.clear();
+        *self = BoardState { undo_stack, ..BoardState::empty() };
+        self.castling_rights = [true, true, true, true];
+
+        self.setup_pieces();
+        self.refresh_hash();
+    }
+
+    /// Creates a board with no pieces, White to move and no castling rights.
+    pub fn empty() -> Self {
         let mut board = BoardState {
             white_pawns: BitBoard::empty(),
             black_pawns: BitBoard::empty(),
@@ -107,14 +252,34 @@ impl BoardState {
             all_black: BitBoard::empty(),
             all_pieces: BitBoard::empty(),
             to_move: PieceColour::White,
-            castling_rights: [true, true, true, true],
+            castling_rights: [false, false, false, false],
             en_passant_square: None,
+            half_move_clock: 0,
+            zobrist: 0,
+            undo_stack: Vec::new(),
         };
-
-        board.setup_pieces();
+        board.refresh_hash();
         board
     }
 
+    /// Zobrist hash of the position, as `ZobristHashing::compute_hash` would give it.
+    pub fn hash(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// Recompute the hash from scratch. Moves, `add_piece` and `remove_piece` keep it up
+    /// to date, so this is only needed after setting fields such as `to_move` directly.
+    pub fn refresh_hash(&mut self) {
+        self.zobrist = ZobristHashing::shared().compute_hash(self);
+    }
+
+    /// The part of the hash for castling rights and the en passant file.
+    fn rights_key(&self) -> u64 {
+        let keys = ZobristHashing::shared();
+        let en_passant_key = self.en_passant_square.map_or(0, |square| keys.en_passant_keys[square % 8]);
+        keys.castling_key(self.castling_rights) ^ en_passant_key
+    }
+
     fn setup_pieces(&mut self) {
         // Set white pawns
         for i in 8..16 {
@@ -152,157 +317,110 @@ impl BoardState {
         self.update_aggregate_bitboards();
     }
 
-    fn update_aggregate_bitboards(&mut self) {
-        self.all_white = BitBoard(
-            self.white_pawns.0
-                | self.white_knights.0
-                | self.white_bishops.0
-                | self.white_rooks.0
-                | self.white_queens.0
-                | self.white_king.0,
-        );
+    pub fn update_aggregate_bitboards(&mut self) {
+        self.all_white = self.colour_occupancy(PieceColour::White);
+        self.all_black = self.colour_occupancy(PieceColour::Black);
+        self.all_pieces = BitBoard(self.all_white.0 | self.all_black.0);
+    }
 
-        self.all_black = BitBoard(
-            self.black_pawns.0
-                | self.black_knights.0
-                | self.black_bishops.0
-                | self.black_rooks.0
-                | self.black_queens.0
-                | self.black_king.0,
-        );
+    /// The bitboard holding the pieces of one kind and colour.
+    pub fn piece_bitboard(&self, kind: PieceKind, colour: PieceColour) -> BitBoard {
+        match (colour, kind) {
+            (PieceColour::White, PieceKind::Pawn) => self.white_pawns,
+            (PieceColour::Black, PieceKind::Pawn) => self.black_pawns,
+            (PieceColour::White, PieceKind::Knight) => self.white_knights,
+            (PieceColour::Black, PieceKind::Knight) => self.black_knights,
+            (PieceColour::White, PieceKind::Bishop) => self.white_bishops,
+            (PieceColour::Black, PieceKind::Bishop) => self.black_bishops,
+            (PieceColour::White, PieceKind::Rook) => self.white_rooks,
+            (PieceColour::Black, PieceKind::Rook) => self.black_rooks,
+            (PieceColour::White, PieceKind::Queen) => self.white_queens,
+            (PieceColour::Black, PieceKind::Queen) => self.black_queens,
+            (PieceColour::White, PieceKind::King) => self.white_king,
+            (PieceColour::Black, PieceKind::King) => self.black_king,
+        }
+    }
 
-        self.all_pieces = BitBoard(self.all_white.0 | self.all_black.0);
+    /// Mutable access to the bitboard holding the pieces of one kind and colour. The
+    /// aggregate bitboards are left for the caller to keep in sync.
+    pub fn piece_bitboard_mut(&mut self, kind: PieceKind, colour: PieceColour) -> &mut BitBoard {
+        match (colour, kind) {
+            (PieceColour::White, PieceKind::Pawn) => &mut self.white_pawns,
+            (PieceColour::Black, PieceKind::Pawn) => &mut self.black_pawns,
+            (PieceColour::White, PieceKind::Knight) => &mut self.white_knights,
+            (PieceColour::Black, PieceKind::Knight) => &mut self.black_knights,
+            (PieceColour::White, PieceKind::Bishop) => &mut self.white_bishops,
+            (PieceColour::Black, PieceKind::Bishop) => &mut self.black_bishops,
+            (PieceColour::White, PieceKind::Rook) => &mut self.white_rooks,
+            (PieceColour::Black, PieceKind::Rook) => &mut self.black_rooks,
+            (PieceColour::White, PieceKind::Queen) => &mut self.white_queens,
+            (PieceColour::Black, PieceKind::Queen) => &mut self.black_queens,
+            (PieceColour::White, PieceKind::King) => &mut self.white_king,
+            (PieceColour::Black, PieceKind::King) => &mut self.black_king,
+        }
     }
 
+    /// Every square holding a piece of the given colour, built from the per-piece bitboards.
+    fn colour_occupancy(&self, colour: PieceColour) -> BitBoard {
+        BitBoard(
+            PieceKind::ALL
+                .iter()
+                .fold(0, |occupied, &kind| occupied | self.piece_bitboard(kind, colour).0),
+        )
+    }
+
+    #[cfg(any(feature = "std", test))]
     pub fn print_board(&self) {
-        let mut squares = [". "; TOTAL_SQUARES];
-
-        for i in 0..TOTAL_SQUARES {
-            if self.white_pawns.is_set(i) {
-                squares[i] = "P ";
-            } else if self.black_pawns.is_set(i) {
-                squares[i] = "p ";
-            } else if self.white_knights.is_set(i) {
-                squares[i] = "N ";
-            } else if self.black_knights.is_set(i) {
-                squares[i] = "n ";
-            } else if self.white_bishops.is_set(i) {
-                squares[i] = "B ";
-            } else if self.black_bishops.is_set(i) {
-                squares[i] = "b ";
-            } else if self.white_rooks.is_set(i) {
-                squares[i] = "R ";
-            } else if self.black_rooks.is_set(i) {
-                squares[i] = "r ";
-            } else if self.white_queens.is_set(i) {
-                squares[i] = "Q ";
-            } else if self.black_queens.is_set(i) {
-                squares[i] = "q ";
-            } else if self.white_king.is_set(i) {
-                squares[i] = "K ";
-            } else if self.black_king.is_set(i) {
-                squares[i] = "k ";
-            }
-        }
+        print!("{}", self);
+    }
 
-        println!("  a b c d e f g h");
-        for rank in (0..BOARD_SIZE).rev() {
-            print!("{} ", rank + 1);
-            for file in 0..BOARD_SIZE {
-                print!("{}", squares[rank * BOARD_SIZE + file]);
-            }
-            println!("");
-        }
-        println!("  a b c d e f g h");
-    }
-
-    pub fn piece_at(&self, square: usize) -> Option<crate::pieces::Piece> {
-        if self.white_pawns.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Pawn,
-                colour: crate::pieces::PieceColour::White,
-            })
-        } else if self.black_pawns.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Pawn,
-                colour: crate::pieces::PieceColour::Black,
-            })
-        } else if self.white_knights.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Knight,
-                colour: crate::pieces::PieceColour::White,
-            })
-        } else if self.black_knights.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Knight,
-                colour: crate::pieces::PieceColour::Black,
-            })
-        } else if self.white_bishops.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Bishop,
-                colour: crate::pieces::PieceColour::White,
-            })
-        } else if self.black_bishops.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Bishop,
-                colour: crate::pieces::PieceColour::Black,
-            })
-        } else if self.white_rooks.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Rook,
-                colour: crate::pieces::PieceColour::White,
-            })
-        } else if self.black_rooks.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Rook,
-                colour: crate::pieces::PieceColour::Black,
-            })
-        } else if self.white_queens.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Queen,
-                colour: crate::pieces::PieceColour::White,
-            })
-        } else if self.black_queens.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::Queen,
-                colour: crate::pieces::PieceColour::Black,
-            })
-        } else if self.white_king.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::King,
-                colour: crate::pieces::PieceColour::White,
-            })
-        } else if self.black_king.is_set(square) {
-            Some(crate::pieces::Piece {
-                kind: crate::pieces::PieceKind::King,
-                colour: crate::pieces::PieceColour::Black,
-            })
-        } else {
-            None
+    /// Print the board from the given side's point of view, with its first rank at the bottom.
+    #[cfg(any(feature = "std", test))]
+    pub fn print_board_as(&self, perspective: PieceColour) {
+        print!("{}", self.display_as(perspective));
+    }
+
+    /// Display the board from the given side's point of view, followed by the side to move,
+    /// castling rights and en passant square.
+    pub fn display_as(&self, perspective: PieceColour) -> BoardDisplay<'_> {
+        BoardDisplay {
+            board: self,
+            perspective,
         }
     }
 
-    pub fn set_piece_at(&mut self, square: usize, piece: Piece) {
-        let bit = 1u64 << square;
+    /// Check whether no piece stands on the square.
+    #[inline]
+    pub fn is_empty(&self, square: usize) -> bool {
+        !self.all_pieces.is_set(square)
+    }
 
-        // Clear the square on all bitboards
-        self.clear_square(square);
+    pub fn piece_at(&self, square: usize) -> Option<Piece> {
+        for kind in PieceKind::ALL {
+            for colour in [PieceColour::White, PieceColour::Black] {
+                if self.piece_bitboard(kind, colour).is_set(square) {
+                    return Some(Piece { kind, colour });
+                }
+            }
+        }
+        None
+    }
 
-        // Set the bit on the appropriate bitboard
-        match (piece.colour, piece.kind) {
-            (PieceColour::White, PieceKind::Pawn) => self.white_pawns |= bit,
-            (PieceColour::Black, PieceKind::Pawn) => self.black_pawns |= bit,
-            (PieceColour::White, PieceKind::Knight) => self.white_knights |= bit,
-            (PieceColour::Black, PieceKind::Knight) => self.black_knights |= bit,
-            (PieceColour::White, PieceKind::Bishop) => self.white_bishops |= bit,
-            (PieceColour::Black, PieceKind::Bishop) => self.black_bishops |= bit,
-            (PieceColour::White, PieceKind::Rook) => self.white_rooks |= bit,
-            (PieceColour::Black, PieceKind::Rook) => self.black_rooks |= bit,
-            (PieceColour::White, PieceKind::Queen) => self.white_queens |= bit,
-            (PieceColour::Black, PieceKind::Queen) => self.black_queens |= bit,
-            (PieceColour::White, PieceKind::King) => self.white_king |= bit,
-            (PieceColour::Black, PieceKind::King) => self.black_king |= bit,
+    /// Put a piece on a square, taking off whatever stood there first. The piece and
+    /// aggregate bitboards and the incremental hash are all kept up to date.
+    pub fn add_piece(&mut self, square: usize, piece: Piece) {
+        if !self.is_empty(square) {
+            self.remove_piece(square);
         }
+        self.toggle_piece(square, piece);
+    }
+
+    /// Take the piece on a square off the board, returning it, or `None` if the square was
+    /// already empty. Keeps the bitboards and hash up to date like `add_piece`.
+    pub fn remove_piece(&mut self, square: usize) -> Option<Piece> {
+        let piece = self.piece_at(square)?;
+        self.toggle_piece(square, piece);
+        Some(piece)
     }
 
     pub fn update_castling_rights(&mut self, wk: bool, wq: bool, bk: bool, bq: bool) {
@@ -312,8 +430,8 @@ impl BoardState {
     /// Check if castling kingside is allowed for the current player.
     pub fn can_castle_kingside(&self, colour: PieceColour) -> bool {
         let (king_square, rook_square, empty_squares, check_squares) = match colour {
-            PieceColour::White => (4, 7, [5, 6], [4, 5, 6]),
-            PieceColour::Black => (60, 63, [61, 62], [60, 61, 62]),
+            PieceColour::White => (4, 7, [5, 6], 0x70),
+            PieceColour::Black => (60, 63, [61, 62], 0x70 << 56),
         };
 
         let rights = match colour {
@@ -322,16 +440,16 @@ impl BoardState {
         };
 
         rights
-            && empty_squares.iter().all(|&sq| !self.all_pieces.is_set(sq))
-            && check_squares.iter().all(|&sq| self.is_square_safe(sq))
+            && empty_squares.iter().all(|&sq| self.is_empty(sq))
             && self.validate_castling_pieces(king_square, rook_square)
+            && self.castling_path_is_safe(colour, check_squares)
     }
 
     /// Check if castling queenside is allowed for the current player.
     pub fn can_castle_queenside(&self, colour: PieceColour) -> bool {
         let (king_square, rook_square, empty_squares, check_squares) = match colour {
-            PieceColour::White => (4, 0, [1, 2, 3], [2, 3, 4]),
-            PieceColour::Black => (60, 56, [57, 58, 59], [58, 59, 60]),
+            PieceColour::White => (4, 0, [1, 2, 3], 0x1C),
+            PieceColour::Black => (60, 56, [57, 58, 59], 0x1C << 56),
         };
     
         let rights = match colour {
@@ -340,239 +458,275 @@ impl BoardState {
         };
     
         rights
-            && empty_squares.iter().all(|&sq| !self.all_pieces.is_set(sq))
-            && check_squares.iter().all(|&sq| self.is_square_safe(sq))
+            && empty_squares.iter().all(|&sq| self.is_empty(sq))
             && self.validate_castling_pieces(king_square, rook_square)
+            && self.castling_path_is_safe(colour, check_squares)
+    }
+
+    /// Check that none of the squares the king starts on, crosses or lands on is attacked by
+    /// the other side. The attack set is built once rather than scanned per square.
+    fn castling_path_is_safe(&self, colour: PieceColour, path: u64) -> bool {
+        (self.attacked_squares(colour.opposite()) & path).0 == 0
     }
 
     /// Helper to check if king and rook are in the correct positions for castling.
     pub fn validate_castling_pieces(&self, king_square: usize, rook_square: usize) -> bool {
-        self.piece_at(king_square).map_or(false, |piece| piece.kind == PieceKind::King)
-            && self.piece_at(rook_square).map_or(false, |piece| piece.kind == PieceKind::Rook)
+        self.piece_at(king_square).is_some_and(|piece| piece.kind == PieceKind::King)
+            && self.piece_at(rook_square).is_some_and(|piece| piece.kind == PieceKind::Rook)
     }
 
-    /// Generic method to validate castling conditions dynamically
-    pub fn king_and_rook_can_castle(&self, king_square: usize, rook_square: usize, empty_squares: &[usize]) -> bool {
-        self.is_square_safe(king_square)
-            && self.is_square_safe(king_square + 1)
-            && self.is_square_safe(king_square + 2)
-            && self.all_pieces.is_set(rook_square) // Rook is present
-            && empty_squares.iter().all(|&sq| !self.all_pieces.is_set(sq)) // Path is clear
+    /// Check that no opponent piece attacks the square.
+    pub fn is_square_safe(&self, square: usize) -> bool {
+        !self.is_square_attacked(square, self.to_move.opposite())
     }
 
-    
-    pub fn is_square_safe(&self, square: usize) -> bool {
-        // Check if the square is attacked by any opponent piece
-        let opponent_colour = self.to_move.opposite();
-    
-        // Check pawn attacks
-        let pawn_attack_offsets = if opponent_colour == PieceColour::White {
-            [-9, -7]
-        } else {
-            [9, 7]
+    pub fn apply_move(&mut self, chess_move: ChessMove) {
+        self.record_move(chess_move);
+    }
+
+    /// Play a move and keep it for `undo_last`, checking in debug builds that it was legal and
+    /// that the incremental hash still matches a full recompute. Every way of playing a move
+    /// onto the undo stack goes through here.
+    fn record_move(&mut self, chess_move: ChessMove) {
+        let undo = self.make_move(chess_move);
+        self.undo_stack.push(undo);
+        self.debug_assert_mover_not_in_check(chess_move);
+
+        debug_assert_eq!(
+            self.zobrist,
+            ZobristHashing::shared().compute_hash(self),
+            "Incremental hash drifted after {:?}",
+            chess_move
+        );
+        tracing::debug!("Updated Zobrist hash: {}", self.zobrist);
+    }
+
+    /// Play a legal move given only by its squares, working out castling, en passant and
+    /// double pushes from the board. A pawn reaching the last rank becomes a queen unless
+    /// another promotion is given.
+    pub fn make_move_from_squares(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<PieceKind>,
+    ) -> Result<(), MoveError> {
+        let piece = self.piece_at(from).ok_or(MoveError::EmptySquare)?;
+        let promotion = match promotion {
+            None if piece.kind == PieceKind::Pawn && to / 8 == piece.colour.promotion_rank() => Some(PieceKind::Queen),
+            promotion => promotion,
         };
-        for &offset in &pawn_attack_offsets {
-            let target = (square as isize + offset) as usize;
-            if target < 64 {
-                if let Some(piece) = self.piece_at(target) {
-                    if piece.kind == PieceKind::Pawn && piece.colour == opponent_colour {
-                        return false;
-                    }
-                }
-            }
-        }
-    
-        // Check knight attacks
-        let knight_offsets = [17, 15, 10, 6, -17, -15, -10, -6];
-        for &offset in &knight_offsets {
-            let target = (square as isize + offset) as usize;
-            if target < 64 {
-                if let Some(piece) = self.piece_at(target) {
-                    if piece.kind == PieceKind::Knight && piece.colour == opponent_colour {
-                        return false;
-                    }
-                }
-            }
-        }
-    
-        // Check sliding piece attacks (bishop, rook, queen)
-        let sliding_directions = &[9, 7, -9, -7, 8, -8, 1, -1];
-        for &direction in sliding_directions {
-            let mut target = square as isize + direction;
-            while target >= 0 && target < 64 {
-                let target_usize = target as usize;
-                if let Some(piece) = self.piece_at(target_usize) {
-                    if piece.colour == opponent_colour {
-                        if (piece.kind == PieceKind::Bishop && [9, 7, -9, -7].contains(&direction))
-                            || (piece.kind == PieceKind::Rook && [8, -8, 1, -1].contains(&direction))
-                            || piece.kind == PieceKind::Queen
-                        {
-                            return false;
-                        }
-                    }
-                    break;
-                }
-                target += direction;
-            }
+
+        let chess_move = ChessMove { from, to, promotion };
+        if !self.generate_legal_moves().contains(&chess_move) {
+            return Err(MoveError::IllegalMove);
         }
-    
-        // Check king attacks
-        let king_offsets = [9, 7, -9, -7, 8, -8, 1, -1];
-        for &offset in &king_offsets {
-            let target = (square as isize + offset) as usize;
-            if target < 64 {
-                if let Some(piece) = self.piece_at(target) {
-                    if piece.kind == PieceKind::King && piece.colour == opponent_colour {
-                        return false;
-                    }
-                }
-            }
+
+        tracing::debug!(?chess_move, "Playing move from squares");
+        self.record_move(chess_move);
+        Ok(())
+    }
+
+    /// Play a sequence of moves in order, each checked as by `make_move_from_squares`. Stops at
+    /// the first move that can't be played and reports its index, keeping the moves before it.
+    pub fn make_moves(&mut self, moves: &[ChessMove]) -> Result<(), MoveError> {
+        for (index, m) in moves.iter().enumerate() {
+            self.make_move_from_squares(m.from, m.to, m.promotion)
+                .map_err(|_| MoveError::IllegalMoveAt(index))?;
         }
-    
-        true
+        Ok(())
+    }
+
+    /// Safety net against illegal moves slipping through: the side that just moved must never
+    /// be left in check. Boards without that side's king are skipped.
+    fn debug_assert_mover_not_in_check(&self, chess_move: ChessMove) {
+        let mover = self.to_move.opposite();
+        let has_king = match mover {
+            PieceColour::White => self.white_king.0 != 0,
+            PieceColour::Black => self.black_king.0 != 0,
+        };
+        debug_assert!(
+            !has_king || !self.is_in_check(mover),
+            "Illegal move {:?} left the {:?} king in check",
+            chess_move,
+            mover
+        );
+    }
+
+    /// Take back the last move played with `apply_move`, returning it.
+    pub fn undo_last(&mut self) -> Option<ChessMove> {
+        let undo = self.undo_stack.pop()?;
+        self.unmake_move(undo);
+        Some(undo.chess_move)
     }
 
-    pub fn apply_move(&mut self, chess_move: ChessMove, zobrist: &mut ZobristHashing) {
+    /// Play a move on the board, handling captures, en passant, castling and promotion.
+    ///
+    /// Returns the record needed to take the move back with `unmake_move`.
+    pub fn make_move(&mut self, chess_move: ChessMove) -> MoveUndo {
+        let mut undo = MoveUndo {
+            chess_move,
+            captured: None,
+            castling_rights: self.castling_rights,
+            en_passant_square: self.en_passant_square,
+            half_move_clock: self.half_move_clock,
+            zobrist: self.zobrist,
+            kind: self.move_kind(&chess_move),
+        };
+        self.zobrist ^= self.rights_key() ^ ZobristHashing::shared().side_to_move_key;
+
+        // A null move just passes the turn
+        if chess_move.is_null() {
+            self.en_passant_square = None;
+            self.flip_turn();
+            self.zobrist ^= self.rights_key();
+            return undo;
+        }
+
         let from = chess_move.from;
         let to = chess_move.to;
-    
-        // Verify that the piece exists before attempting to move
-        let piece = self.piece_at(from).expect("Piece must exist at 'from'");
-    
+
         // Update en passant square before clearing 'from'
         self.update_en_passant_square(&chess_move);
-    
-        // Move the piece
-        self.clear_square(from);
-        self.set_piece_at(to, piece);
-    
-        // Handle special moves (e.g., en passant, promotion)
-        if piece.kind == PieceKind::Pawn {
-            if let Some(ep_square) = self.en_passant_square {
-                if to == ep_square {
-                    let captured_square = if piece.colour == PieceColour::White {
-                        to - 8 // Black pawn behind
-                    } else {
-                        to + 8 // White pawn behind
-                    };
-                    self.clear_square(captured_square);
-                }
-            }
-            if let Some(promotion) = chess_move.promotion {
-                self.clear_square(to);
-                self.set_piece_at(to, Piece {
-                    kind: promotion,
-                    colour: piece.colour,
-                });
-            }
+
+        // Move the piece, removing anything captured on 'to'
+        let piece = self.remove_piece(from).expect("Piece must exist at 'from'");
+        undo.captured = self.remove_piece(to);
+        self.add_piece(to, Piece {
+            kind: chess_move.promotion.unwrap_or(piece.kind),
+            colour: piece.colour,
+        });
+
+        // En passant takes the pawn from behind the target square
+        if undo.kind.is_en_passant() {
+            let captured_square = Self::en_passant_victim_square(to, piece.colour);
+            undo.captured = self.remove_piece(captured_square);
+        }
+
+        // Castling also moves the rook to the other side of the king
+        if undo.kind.is_castle() {
+            let (rook_from, rook_to) = Self::castling_rook_squares(from, to);
+            self.move_piece(rook_from, rook_to);
         }
-    
-        // Flip the turn and update hash
-        self.flip_turn();
-        let new_hash = zobrist.compute_hash(self);
-        tracing::debug!("Updated Zobrist hash: {}", new_hash);
-    }
-    
 
-    fn get_piece_at_square(&self, square: usize) -> Option<Piece> {
-        if self.white_pawns.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Pawn,
-                colour: PieceColour::White,
-            })
-        } else if self.black_pawns.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Pawn,
-                colour: PieceColour::Black,
-            })
-        } else if self.white_knights.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Knight,
-                colour: PieceColour::White,
-            })
-        } else if self.black_knights.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Knight,
-                colour: PieceColour::Black,
-            })
-        } else if self.white_bishops.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Bishop,
-                colour: PieceColour::White,
-            })
-        } else if self.black_bishops.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Bishop,
-                colour: PieceColour::Black,
-            })
-        } else if self.white_rooks.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Rook,
-                colour: PieceColour::White,
-            })
-        } else if self.black_rooks.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Rook,
-                colour: PieceColour::Black,
-            })
-        } else if self.white_queens.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Queen,
-                colour: PieceColour::White,
-            })
-        } else if self.black_queens.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::Queen,
-                colour: PieceColour::Black,
-            })
-        } else if self.white_king.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::King,
-                colour: PieceColour::White,
-            })
-        } else if self.black_king.is_set(square) {
-            Some(Piece {
-                kind: PieceKind::King,
-                colour: PieceColour::Black,
-            })
+        // Pawn moves (promotions included) and captures (en passant included) are irreversible
+        if piece.kind == PieceKind::Pawn || undo.captured.is_some() {
+            self.half_move_clock = 0;
         } else {
-            None
+            self.half_move_clock = self.half_move_clock.saturating_add(1);
         }
+
+        self.update_castling_rights_for_move(from, to);
+        self.flip_turn();
+        self.zobrist ^= self.rights_key();
+        self.debug_assert_aggregates_in_sync();
+
+        undo
     }
-    
 
-    fn clear_square(&mut self, square: usize) {
-        self.white_pawns.clear(square);
-        self.black_pawns.clear(square);
-        self.all_white.clear(square);
-        self.all_black.clear(square);
-        self.all_pieces.clear(square);
+    /// Take back a move made with `make_move`.
+    pub fn unmake_move(&mut self, undo: MoveUndo) {
+        self.flip_turn();
+        self.castling_rights = undo.castling_rights;
+        self.en_passant_square = undo.en_passant_square;
+        self.half_move_clock = undo.half_move_clock;
+
+        let chess_move = undo.chess_move;
+        if chess_move.is_null() {
+            self.zobrist = undo.zobrist;
+            return;
+        }
+
+        let from = chess_move.from;
+        let to = chess_move.to;
+        let moved = self.remove_piece(to).expect("Moved piece must exist at 'to'");
+        let piece = Piece {
+            kind: if chess_move.is_promotion() { PieceKind::Pawn } else { moved.kind },
+            colour: moved.colour,
+        };
+        self.add_piece(from, piece);
+
+        if let Some(captured) = undo.captured {
+            let captured_square = if undo.kind.is_en_passant() {
+                Self::en_passant_victim_square(to, piece.colour)
+            } else {
+                to
+            };
+            self.add_piece(captured_square, captured);
+        }
+
+        if undo.kind.is_castle() {
+            let (rook_from, rook_to) = Self::castling_rook_squares(from, to);
+            self.move_piece(rook_to, rook_from);
+        }
+        self.zobrist = undo.zobrist;
+        self.debug_assert_aggregates_in_sync();
     }
 
-    fn set_square(&mut self, square: usize, piece: Piece) {
+    /// Flip a piece's bit on its own bitboard and on the aggregates, so it appears on an
+    /// empty square or disappears from the square it stands on. The hash follows along.
+    /// Only `add_piece` and `remove_piece` call this, having checked what the square holds.
+    fn toggle_piece(&mut self, square: usize, piece: Piece) {
+        let keys = ZobristHashing::shared();
+        self.zobrist ^= keys.piece_keys[piece.colour.index()][piece.kind.index()][square];
+        self.piece_bitboard_mut(piece.kind, piece.colour).toggle(square);
         match piece.colour {
-            PieceColour::White => {
-                self.all_white.set(square);
-                match piece.kind {
-                    PieceKind::Pawn => self.white_pawns.set(square),
-                    _ => { /* Set other piece types here */ }
+            PieceColour::White => self.all_white.toggle(square),
+            PieceColour::Black => self.all_black.toggle(square),
+        }
+        self.all_pieces.toggle(square);
+    }
+
+    /// Move a piece to an empty square, such as the rook crossing the king when castling.
+    fn move_piece(&mut self, from: usize, to: usize) {
+        let piece = self.remove_piece(from).expect("Piece must exist at 'from'");
+        debug_assert!(self.is_empty(to), "Square {} is occupied", to);
+        self.add_piece(to, piece);
+    }
+
+    /// The aggregate bitboards are maintained incrementally, so check in debug builds that
+    /// they still agree with the piece bitboards.
+    fn debug_assert_aggregates_in_sync(&self) {
+        let white = self.colour_occupancy(PieceColour::White);
+        let black = self.colour_occupancy(PieceColour::Black);
+        debug_assert!(
+            self.all_white == white && self.all_black == black && self.all_pieces.0 == white.0 | black.0,
+            "Aggregate bitboards out of sync"
+        );
+    }
+
+    /// The square of the pawn removed by an en passant capture landing on `to`.
+    fn en_passant_victim_square(to: usize, capturer: PieceColour) -> usize {
+        (to as isize - capturer.pawn_push_offset()) as usize
+    }
+
+    /// The rook's (from, to) squares for a castling king move.
+    fn castling_rook_squares(king_from: usize, king_to: usize) -> (usize, usize) {
+        if king_to > king_from {
+            (king_from + 3, king_from + 1)
+        } else {
+            (king_from - 4, king_from - 1)
+        }
+    }
+
+    /// Remove castling rights when a king or rook leaves its starting square, or a rook is captured there.
+    fn update_castling_rights_for_move(&mut self, from: usize, to: usize) {
+        for square in [from, to] {
+            match square {
+                4 => {
+                    self.castling_rights[0] = false;
+                    self.castling_rights[1] = false;
                 }
-            }
-            PieceColour::Black => {
-                self.all_black.set(square);
-                match piece.kind {
-                    PieceKind::Pawn => self.black_pawns.set(square),
-                    _ => { /* Set other piece types here */ }
+                7 => self.castling_rights[0] = false,
+                0 => self.castling_rights[1] = false,
+                60 => {
+                    self.castling_rights[2] = false;
+                    self.castling_rights[3] = false;
                 }
+                63 => self.castling_rights[2] = false,
+                56 => self.castling_rights[3] = false,
+                _ => {}
             }
         }
-        self.all_pieces.set(square);
-    }
-
-    fn promote_pawn(&mut self, square: usize, promotion: PieceKind) {
-        // Handle promotion by clearing the pawn and setting the promoted piece
-        self.clear_square(square);
-        self.set_square(square, Piece { kind: promotion, colour: self.to_move });
     }
 
     pub fn flip_turn(&mut self) {
@@ -594,8 +748,14 @@ impl BoardState {
         if let Some(piece) = self.piece_at(chess_move.from) {
             tracing::debug!("Piece at 'from': {:?}", piece);
 
-            if piece.kind == PieceKind::Pawn && (to_rank as isize - from_rank as isize).abs() == 2 {
-                self.en_passant_square = Some((chess_move.from + chess_move.to) / 2);
+            // Only keep the square when an enemy pawn can capture on it, as `from_fen` does,
+            // so the same position always hashes the same
+            let ep_square = (chess_move.from + chess_move.to) / 2;
+            if piece.kind == PieceKind::Pawn
+                && (to_rank as isize - from_rank as isize).abs() == 2
+                && self.en_passant_capturable(ep_square, piece.colour.opposite())
+            {
+                self.en_passant_square = Some(ep_square);
                 tracing::debug!("En passant square set to: {:?}", self.en_passant_square);
                 return;
             }
@@ -611,28 +771,119 @@ impl BoardState {
         self.en_passant_square = None;
     }
 
-    /// Validate en passant move legality.
-    fn is_valid_en_passant(&self, from: usize, to: usize) -> bool {
-        if let Some(ep_square) = self.en_passant_square {
-            return to == ep_square;
+    /// Check whether a pawn of the given colour can capture onto the en passant square.
+    pub(crate) fn en_passant_capturable(&self, ep_square: usize, by: PieceColour) -> bool {
+        // The double-pushed pawn sits on the square just past the en passant square
+        let (pushed_square, capturers) = match by {
+            PieceColour::White => (ep_square - BOARD_SIZE, &self.white_pawns),
+            PieceColour::Black => (ep_square + BOARD_SIZE, &self.black_pawns),
+        };
+
+        let file = pushed_square % BOARD_SIZE;
+        (file > 0 && capturers.is_set(pushed_square - 1))
+            || (file < BOARD_SIZE - 1 && capturers.is_set(pushed_square + 1))
+    }
+
+}
+
+impl fmt::Display for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.display_as(PieceColour::White))
+    }
+}
+
+/// A board rendered from one side's point of view, see `BoardState::display_as`.
+pub struct BoardDisplay<'a> {
+    board: &'a BoardState,
+    perspective: PieceColour,
+}
+
+impl fmt::Display for BoardDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (ranks, files): (Vec<usize>, Vec<usize>) = match self.perspective {
+            PieceColour::White => ((0..BOARD_SIZE).rev().collect(), (0..BOARD_SIZE).collect()),
+            PieceColour::Black => ((0..BOARD_SIZE).collect(), (0..BOARD_SIZE).rev().collect()),
+        };
+        let file_labels: String = files.iter().map(|&file| format!(" {}", (b'a' + file as u8) as char)).collect();
+
+        writeln!(f, " {}", file_labels)?;
+        for &rank in &ranks {
+            write!(f, "{} ", rank + 1)?;
+            for &file in &files {
+                let symbol = self.board.piece_at(rank * BOARD_SIZE + file).map_or('.', piece_char);
+                write!(f, "{} ", symbol)?;
+            }
+            writeln!(f)?;
         }
-        false
+        writeln!(f, " {}", file_labels)?;
+
+        let castling: String = ['K', 'Q', 'k', 'q']
+            .iter()
+            .zip(self.board.castling_rights)
+            .filter(|&(_, right)| right)
+            .map(|(&symbol, _)| symbol)
+            .collect();
+        writeln!(
+            f,
+            "{:?} to move, castling {}, en passant {}",
+            self.board.to_move,
+            if castling.is_empty() { "-".to_string() } else { castling },
+            self.board.en_passant_square.map_or("-".to_string(), square_name)
+        )
+    }
+}
+
+/// FEN letter for a piece, upper case for White.
+fn piece_char(piece: Piece) -> char {
+    let symbol = match piece.kind {
+        PieceKind::Pawn => 'p',
+        PieceKind::Knight => 'n',
+        PieceKind::Bishop => 'b',
+        PieceKind::Rook => 'r',
+        PieceKind::Queen => 'q',
+        PieceKind::King => 'k',
+    };
+    match piece.colour {
+        PieceColour::White => symbol.to_ascii_uppercase(),
+        PieceColour::Black => symbol,
     }
+}
 
+impl Default for BoardState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
+/// Boards are equal when they hold the same position, whatever moves were played to reach it.
+/// The aggregates and the hash follow from the pieces, and the undo stack is history.
+impl PartialEq for BoardState {
+    fn eq(&self, other: &Self) -> bool {
+        [PieceColour::White, PieceColour::Black].into_iter().all(|colour| {
+            PieceKind::ALL
+                .into_iter()
+                .all(|kind| self.piece_bitboard(kind, colour) == other.piece_bitboard(kind, colour))
+        }) && self.to_move == other.to_move
+            && self.castling_rights == other.castling_rights
+            && self.en_passant_square == other.en_passant_square
+            && self.half_move_clock == other.half_move_clock
+    }
+}
+
+impl Eq for BoardState {}
+
 pub struct BitBoardIter {
     bitboard: BitBoard,
     index: usize,
 }
 
 impl Iterator for BitBoardIter {
-    type Item = usize;
+    type Item = Square;
 
     fn next(&mut self) -> Option<Self::Item> {
         while self.index < 64 {
             if self.bitboard.is_set(self.index) {
-                let result = self.index;
+                let result = Square::new(self.index);
                 self.index += 1;
                 return Some(result);
             }
@@ -665,6 +916,17 @@ mod tests {
         assert!(board.black_pawns.is_set(48)); // a7
     }
 
+    #[test]
+    fn test_reset() {
+        let mut board = BoardState::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 40").unwrap();
+        board.apply_move(ChessMove { from: 36, to: 43, promotion: None });
+
+        board.reset();
+        assert_eq!(board, BoardState::new());
+        assert_eq!(board.hash(), BoardState::new().hash());
+        assert_eq!(board.undo_last(), None);
+    }
+
     #[test]
     fn test_bitboard_operations() {
         let mut bitboard = BitBoard::empty();
@@ -678,6 +940,48 @@ mod tests {
         assert!(!bitboard.is_set(0));
     }
 
+    #[test]
+    fn test_toggle() {
+        let mut bitboard = BitBoard((1 << 12) | (1 << 40));
+        let original = bitboard;
+
+        bitboard.toggle(27);
+        assert!(bitboard.is_set(27));
+        bitboard.toggle(27);
+        assert_eq!(bitboard, original);
+
+        bitboard.toggle(12);
+        assert_eq!(bitboard, BitBoard(1 << 40));
+    }
+
+    #[test]
+    fn test_shift() {
+        // a1, d4 and h8
+        let bitboard = BitBoard(1 | (1 << 27) | (1 << 63));
+
+        // Straight up: h8 falls off the top
+        assert_eq!(bitboard.shift(8), BitBoard((1 << 8) | (1 << 35)));
+        // Right: h8 would wrap onto the next rank, so it is dropped
+        assert_eq!(bitboard.shift(1), BitBoard((1 << 1) | (1 << 28)));
+        // Down and left: a1 leaves the board either way
+        assert_eq!(bitboard.shift(-9), BitBoard((1 << 18) | (1 << 54)));
+        // A knight's jump two files left from a1 or h8 wraps, from d4 lands on b5
+        assert_eq!(bitboard.shift(6), BitBoard(1 << 33));
+        assert_eq!(bitboard.shift(0), bitboard);
+        assert_eq!(BitBoard(u64::MAX).shift(56), BitBoard(0xFF << 56));
+        assert_eq!(BitBoard(1).shift(-128), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_step_stays_on_board() {
+        assert_eq!(step(7, 0, 1), None); // h1 doesn't wrap to a2
+        assert_eq!(step(0, 0, -1), None); // a1 doesn't wrap to h-file
+        assert_eq!(step(60, 1, 0), None); // e8 off the top
+        assert_eq!(step(4, -1, 0), None); // e1 off the bottom
+        assert_eq!(step(12, 2, 0), Some(28)); // e2 -> e4
+        assert_eq!(step(6, 2, -1), Some(21)); // g1 -> f3
+    }
+
     #[test]
     fn test_aggregate_bitboards() {
         let board = BoardState::new();
@@ -696,11 +1000,11 @@ mod tests {
     #[test]
     fn test_en_passant_generation() {
         let mut board = BoardState::new();
-        let mut zobrist = ZobristHashing::new();
 
         tracing::debug!("Setting up test board state");
         board.black_pawns.set(51); // d7
         board.all_pieces.set(51);
+        board.add_piece(36, Piece { kind: PieceKind::Pawn, colour: PieceColour::White }); // e5, to take en passant
 
         tracing::debug!("Board state before move: {:?}", board);
 
@@ -710,7 +1014,7 @@ mod tests {
             promotion: None,
         };
 
-        board.apply_move(chess_move, &mut zobrist);
+        board.apply_move(chess_move);
 
         tracing::debug!(
             "En passant square after move: {:?}, Board state: {:?}",
@@ -733,6 +1037,7 @@ mod tests {
         tracing::debug!("Setting up test board state for en passant");
         board.black_pawns.set(51); // d7
         board.all_pieces.set(51);
+        board.add_piece(36, Piece { kind: PieceKind::Pawn, colour: PieceColour::White }); // e5, to take en passant
     
         let chess_move = ChessMove {
             from: 51, // d7
@@ -756,6 +1061,7 @@ mod tests {
     
         board.black_pawns.set(51); // d7
         board.all_pieces.set(51);
+        board.add_piece(36, Piece { kind: PieceKind::Pawn, colour: PieceColour::White }); // e5, to take en passant
     
         assert!(board.black_pawns.is_set(51), "Black pawn should be on d7");
         assert!(board.all_pieces.is_set(51), "All pieces should include pawn on d7");
@@ -766,7 +1072,7 @@ mod tests {
             promotion: None,
         };
     
-        board.apply_move(chess_move, &mut ZobristHashing::new());
+        board.apply_move(chess_move);
     
         assert_eq!(
             board.en_passant_square,
@@ -776,6 +1082,360 @@ mod tests {
     }
     
 
+    #[test]
+    fn test_apply_null_move() {
+        let mut board = BoardState::new();
+        board.en_passant_square = Some(20); // e3
+        board.refresh_hash();
+
+        board.apply_move(ChessMove::null());
+
+        assert_eq!(board.to_move, PieceColour::Black);
+        assert_eq!(board.en_passant_square, None);
+        assert_eq!(board.all_pieces, BoardState::new().all_pieces);
+    }
+
+    #[test]
+    fn test_undo_last_restores_start_position() {
+        let mut board = BoardState::new();
+
+        board.apply_move(ChessMove { from: 12, to: 28, promotion: None }); // e2e4
+        board.apply_move(ChessMove { from: 51, to: 35, promotion: None }); // d7d5
+        board.apply_move(ChessMove { from: 28, to: 35, promotion: None }); // e4xd5
+        assert_ne!(board, BoardState::new());
+
+        assert_eq!(board.undo_last(), Some(ChessMove { from: 28, to: 35, promotion: None }));
+        assert!(board.undo_last().is_some());
+        assert!(board.undo_last().is_some());
+
+        assert_eq!(board, BoardState::new());
+        assert_eq!(board.undo_last(), None);
+    }
+
+    #[test]
+    fn test_equality_ignores_move_history() {
+        let mut played = BoardState::new();
+        played.apply_move(ChessMove { from: 6, to: 21, promotion: None }); // g1f3
+        played.apply_move(ChessMove { from: 62, to: 45, promotion: None }); // g8f6
+        played.apply_move(ChessMove { from: 21, to: 6, promotion: None }); // f3g1
+        played.apply_move(ChessMove { from: 45, to: 62, promotion: None }); // f6g8
+
+        let mut fresh = BoardState::new();
+        fresh.half_move_clock = 4;
+        fresh.refresh_hash();
+        assert_eq!(played, fresh);
+
+        // The same pieces with the other side to move are a different position
+        fresh.flip_turn();
+        assert_ne!(played, fresh);
+    }
+
+    #[test]
+    fn test_add_and_remove_piece() {
+        let zobrist = ZobristHashing::new();
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let before = board.clone();
+        let knight = Piece { kind: PieceKind::Knight, colour: PieceColour::Black };
+
+        board.add_piece(27, knight); // d4
+        assert_eq!(board.piece_at(27), Some(knight));
+        assert!(board.black_knights.is_set(27) && board.all_black.is_set(27) && board.all_pieces.is_set(27));
+        assert_eq!(board.hash(), zobrist.compute_hash(&board));
+        assert_ne!(board.hash(), before.hash());
+
+        assert_eq!(board.remove_piece(27), Some(knight));
+        assert_eq!(board, before);
+        assert_eq!(board.hash(), before.hash());
+        assert_eq!(board.remove_piece(27), None);
+
+        // Adding onto an occupied square replaces the piece there
+        let queen = Piece { kind: PieceKind::Queen, colour: PieceColour::White };
+        board.add_piece(60, queen); // e8
+        assert_eq!(board.piece_at(60), Some(queen));
+        assert!(!board.black_king.is_set(60) && !board.all_black.is_set(60));
+        assert_eq!(board.hash(), zobrist.compute_hash(&board));
+    }
+
+    #[test]
+    fn test_piece_bitboard() {
+        let mut board = BoardState::new();
+        assert_eq!(board.piece_bitboard(PieceKind::Pawn, PieceColour::White), board.white_pawns);
+        assert_eq!(board.piece_bitboard(PieceKind::Queen, PieceColour::Black), board.black_queens);
+
+        board.piece_bitboard_mut(PieceKind::Knight, PieceColour::Black).clear(57);
+        assert!(!board.black_knights.is_set(57));
+    }
+
+    #[test]
+    fn test_bitboard_conversions_and_squares() {
+        let squares: Vec<Square> = BitBoard::from(0b101).iter().collect();
+        assert_eq!(squares, vec![Square::new(0), Square::new(2)]);
+        assert_eq!(squares[1].index(), 2);
+        assert_eq!(squares[1].to_string(), "c1");
+
+        let bits: u64 = BitBoard::from(0xFF).into();
+        assert_eq!(bits, 0xFF);
+    }
+
+    /// Walk every line to the given depth, checking the cached hash against a full recompute
+    /// after each move and after taking it back.
+    fn assert_hash_in_sync(board: &mut BoardState, zobrist: &ZobristHashing, depth: u32) {
+        if depth == 0 {
+            return;
+        }
+        for m in board.generate_legal_moves() {
+            let before = board.hash();
+            let undo = board.make_move(m);
+            assert_eq!(board.hash(), zobrist.compute_hash(board), "after {}", m);
+            assert_hash_in_sync(board, zobrist, depth - 1);
+            board.unmake_move(undo);
+            assert_eq!(board.hash(), before, "after taking back {}", m);
+        }
+    }
+
+    #[test]
+    fn test_cached_hash() {
+        let zobrist = ZobristHashing::new();
+        assert_eq!(BoardState::new().hash(), zobrist.compute_hash(&BoardState::new()));
+
+        // Castling, en passant, promotions and null moves all along the way
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let mut board = BoardState::from_fen(fen).unwrap();
+            assert_eq!(board.hash(), zobrist.compute_hash(&board));
+            assert_hash_in_sync(&mut board, &zobrist, 2);
+        }
+
+        let mut board = BoardState::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let undo = board.make_move(ChessMove::null());
+        assert_eq!(board.hash(), zobrist.compute_hash(&board));
+        board.unmake_move(undo);
+        assert_eq!(board.hash(), zobrist.compute_hash(&board));
+
+        // Editing squares keeps it up to date too
+        board.add_piece(0, Piece { kind: PieceKind::Rook, colour: PieceColour::White });
+        board.remove_piece(35);
+        assert_eq!(board.hash(), zobrist.compute_hash(&board));
+    }
+
+    #[test]
+    fn test_en_passant_hash_through_make_and_unmake() {
+        let zobrist = ZobristHashing::new();
+        let mut board = BoardState::new();
+        let start = board.hash();
+
+        // Nothing can take e4 or e5 en passant, so those pushes leave no square. d5 next to the
+        // e5 pawn creates d6, and Nf3 clears it again.
+        let mut undos = Vec::new();
+        let mut hashes = vec![start];
+        let moves = [("e2e4", None), ("a7a6", None), ("e4e5", None), ("d7d5", Some(43)), ("g1f3", None)];
+        for (text, en_passant) in moves {
+            undos.push(board.make_move(ChessMove::from_uci(text).unwrap()));
+            assert_eq!(board.en_passant_square, en_passant);
+            assert_eq!(board.hash(), zobrist.compute_hash(&board), "after {}", text);
+            hashes.push(board.hash());
+        }
+
+        // Unmaking brings back each earlier hash exactly, en passant square and all
+        while let Some(undo) = undos.pop() {
+            hashes.pop();
+            board.unmake_move(undo);
+            assert_eq!(Some(&board.hash()), hashes.last());
+        }
+        assert_eq!(board.hash(), start);
+        assert_eq!(board.en_passant_square, None);
+    }
+
+    #[test]
+    fn test_uncapturable_double_push_matches_fen() {
+        // After e4 no black pawn stands next to it, so the board is the one the FEN gives,
+        // which drops the e3 square
+        let mut board = BoardState::new();
+        board.make_move(ChessMove::from_uci("e2e4").unwrap());
+
+        let expected =
+            BoardState::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert_eq!(board.en_passant_square, None);
+        assert_eq!(board, expected);
+        assert_eq!(board.hash(), expected.hash());
+
+        // So knights going out and back repeat the position after e4
+        for text in ["g8f6", "g1f3", "f6g8", "f3g1"] {
+            board.make_move(ChessMove::from_uci(text).unwrap());
+        }
+        assert_eq!(board.hash(), expected.hash());
+    }
+
+    #[test]
+    fn test_try_set() {
+        let mut bitboard = BitBoard::empty();
+        assert_eq!(bitboard.try_set(63), Ok(()));
+        assert_eq!(bitboard.try_set(64), Err(OutOfRange(64)));
+        assert_eq!(bitboard, BitBoard(1 << 63));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Square 70 is off the board")]
+    fn test_set_off_the_board_panics() {
+        BitBoard::empty().set(70);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Square 64 is off the board")]
+    fn test_is_set_off_the_board_panics() {
+        BitBoard::empty().is_set(64);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let board = BoardState::new();
+        assert!(board.is_empty(28)); // e4
+        assert!(!board.is_empty(12)); // e2
+    }
+
+    #[test]
+    fn test_half_move_clock() {
+        let mut board = BoardState::from_fen("4k3/8/8/3p4/4P3/8/8/R3K1N1 w - - 7 20").unwrap();
+        assert_eq!(board.half_move_clock, 7);
+
+        // A quiet piece move ticks the clock, the capture resets it, and unmaking restores it
+        board.make_move(ChessMove { from: 6, to: 21, promotion: None }); // Nf3
+        assert_eq!(board.half_move_clock, 8);
+        board.make_move(ChessMove { from: 60, to: 59, promotion: None }); // Kd8
+        let undo_capture = board.make_move(ChessMove { from: 28, to: 35, promotion: None }); // exd5
+        assert_eq!(board.half_move_clock, 0);
+
+        board.unmake_move(undo_capture);
+        assert_eq!(board.half_move_clock, 9);
+
+        // En passant and promotions are pawn moves too
+        for (fen, chess_move) in [
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 5 1", ChessMove { from: 36, to: 43, promotion: None }),
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 5 1", ChessMove { from: 48, to: 56, promotion: Some(PieceKind::Queen) }),
+        ] {
+            let mut board = BoardState::from_fen(fen).unwrap();
+            let undo = board.make_move(chess_move);
+            assert_eq!(board.half_move_clock, 0);
+            board.unmake_move(undo);
+            assert_eq!(board.half_move_clock, 5);
+        }
+
+        // A clock read from a FEN can be anything, so it stops at the top rather than wrapping
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/8/8/4K1N1 w - - 65535 1").unwrap();
+        board.make_move(ChessMove { from: 6, to: 21, promotion: None }); // Nf3
+        assert_eq!(board.half_move_clock, u16::MAX);
+    }
+
+    #[test]
+    fn test_display_orientation() {
+        let board = BoardState::from_fen("4k3/8/8/3pP3/8/8/8/R3K3 w Q d6 0 1").unwrap();
+
+        let white_view = board.to_string();
+        let lines: Vec<&str> = white_view.lines().collect();
+        assert_eq!(lines[0], "  a b c d e f g h");
+        assert_eq!(lines[1], "8 . . . . k . . . ");
+        assert_eq!(lines[8], "1 R . . . K . . . ");
+        assert_eq!(lines[10], "White to move, castling Q, en passant d6");
+
+        // From Black's side rank 1 is on top and the files run from h to a
+        let black_view = board.display_as(PieceColour::Black).to_string();
+        let lines: Vec<&str> = black_view.lines().collect();
+        assert_eq!(lines[0], "  h g f e d c b a");
+        assert_eq!(lines[1], "1 . . . K . . . R ");
+        assert_eq!(lines[8], "8 . . . k . . . . ");
+        assert_eq!(lines[10], "White to move, castling Q, en passant d6");
+    }
+
+    #[test]
+    fn test_make_move_from_squares() {
+        let mut board = BoardState::from_fen("r3k2r/8/8/8/8/8/1p6/R3K2R w KQkq - 0 1").unwrap();
+
+        // Moving the king two squares castles
+        assert_eq!(board.make_move_from_squares(4, 6, None), Ok(()));
+        assert!(board.white_king.is_set(6));
+        assert!(board.white_rooks.is_set(5));
+        assert!(!board.castling_rights[0] && !board.castling_rights[1]);
+
+        // A pawn reaching the last rank promotes to a queen by default
+        assert_eq!(board.make_move_from_squares(9, 1, None), Ok(()));
+        assert!(board.black_queens.is_set(1));
+
+        assert_eq!(board.make_move_from_squares(20, 28, None), Err(MoveError::EmptySquare));
+        assert_eq!(board.make_move_from_squares(6, 22, None), Err(MoveError::IllegalMove));
+
+        assert!(board.undo_last().is_some());
+        assert!(board.undo_last().is_some());
+        assert_eq!(board, BoardState::from_fen("r3k2r/8/8/8/8/8/1p6/R3K2R w KQkq - 0 1").unwrap());
+    }
+
+    #[test]
+    fn test_make_moves() {
+        let line: Vec<ChessMove> = ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]
+            .iter()
+            .map(|text| ChessMove::from_uci(text).unwrap())
+            .collect();
+
+        let mut board = BoardState::new();
+        assert_eq!(board.make_moves(&line), Ok(()));
+        assert_eq!(
+            board.hash(),
+            BoardState::from_fen("r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3")
+                .unwrap()
+                .hash()
+        );
+
+        // White can't move twice in a row; the moves before it stay on the board
+        let mut board = BoardState::new();
+        let mut bad_line = line.clone();
+        bad_line[2] = ChessMove::from_uci("d2d4").unwrap();
+        bad_line[3] = ChessMove::from_uci("d4d5").unwrap();
+        assert_eq!(board.make_moves(&bad_line), Err(MoveError::IllegalMoveAt(3)));
+        assert_eq!(board.to_move, PieceColour::Black);
+    }
+
+    #[test]
+    fn test_unmake_restores_special_moves() {
+        // En passant, castling and promotion are all available to white here
+        let fen = "r3k3/1P6/8/3pP3/8/8/8/R3K2R w KQq d6 0 1";
+        let original = BoardState::from_fen(fen).unwrap();
+        let mut board = BoardState::from_fen(fen).unwrap();
+
+        for chess_move in [
+            ChessMove { from: 36, to: 43, promotion: None },                   // e5xd6 e.p.
+            ChessMove { from: 4, to: 6, promotion: None },                     // O-O
+            ChessMove { from: 4, to: 2, promotion: None },                     // O-O-O
+            ChessMove { from: 49, to: 56, promotion: Some(PieceKind::Knight) }, // b7xa8=N
+        ] {
+            let undo = board.make_move(chess_move);
+            assert_ne!(board, original);
+            board.unmake_move(undo);
+            assert_eq!(board, original, "unmake of {:?} did not restore the board", chess_move);
+        }
+    }
+
+    #[test]
+    fn test_apply_legal_move_passes_check_assertion() {
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/8/4R3/4K3 b - - 0 1").unwrap();
+
+        // Sidestepping the rook is fine
+        board.apply_move(ChessMove { from: 60, to: 59, promotion: None });
+        assert_eq!(board.to_move, PieceColour::White);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "left the Black king in check")]
+    fn test_apply_illegal_move_trips_check_assertion() {
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/8/4R3/4K3 b - - 0 1").unwrap();
+
+        // Staying on the e-file walks into the rook
+        board.apply_move(ChessMove { from: 60, to: 52, promotion: None });
+    }
+
     #[test]
     fn test_castling_rights() {
         let mut board = BoardState::new();
@@ -801,8 +1461,9 @@ mod tests {
         board.all_pieces.clear(5); // f1
         board.all_pieces.clear(6); // g1
 
-        // Place an opposing rook attacking f1
-        board.set_piece_at(37, Piece { kind: PieceKind::Rook, colour: PieceColour::Black });
+        // Open the f-file and place an opposing rook attacking f1
+        board.remove_piece(13); // f2
+        board.add_piece(37, Piece { kind: PieceKind::Rook, colour: PieceColour::Black });
 
         assert!(!board.can_castle_kingside(PieceColour::White), "Should not allow kingside castling if f1 is under attack");
     }
@@ -816,11 +1477,29 @@ mod tests {
         board.all_pieces.clear(2); // c1
         board.all_pieces.clear(3); // d1
 
-        // Place an opposing bishop attacking c1
-        board.set_piece_at(42, Piece { kind: PieceKind::Bishop, colour: PieceColour::Black });
+        // Open the c1-h6 diagonal and place an opposing bishop attacking c1
+        board.remove_piece(11); // d2
+        board.add_piece(20, Piece { kind: PieceKind::Bishop, colour: PieceColour::Black });
 
         assert!(!board.can_castle_queenside(PieceColour::White), "Should not allow queenside castling if c1 is under attack");
     }
 
+    #[test]
+    fn test_castling_blocked_by_attacked_transit_square() {
+        // The h3 bishop covers f1 but none of the queenside path
+        let board = BoardState::from_fen("r3k2r/8/8/8/8/7b/8/R3K2R w KQkq - 0 1").unwrap();
+        let attacked = board.attacked_squares(PieceColour::Black);
+        assert!(attacked.is_set(5));
+        assert!(!attacked.is_set(2) && !attacked.is_set(3) && !attacked.is_set(4) && !attacked.is_set(6));
+        assert!(!board.can_castle_kingside(PieceColour::White));
+        assert!(board.can_castle_queenside(PieceColour::White));
+
+        // The path is checked against the castling side's opponent, not the side to move
+        let board = BoardState::from_fen("r3k2r/8/8/8/8/8/8/R3K1R1 w kq - 0 1").unwrap();
+        assert!(board.attacked_squares(PieceColour::White).is_set(62));
+        assert!(!board.can_castle_kingside(PieceColour::Black));
+        assert!(board.can_castle_queenside(PieceColour::Black));
+    }
+
 
 }
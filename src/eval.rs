@@ -0,0 +1,694 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::attacks::pawn_attacks;
+use crate::board::{step, BitBoard, BoardState, Square, FILE_A, TOTAL_SQUARES};
+use crate::moves::ChessMove;
+use crate::pieces::{PieceColour, PieceKind};
+
+pub use crate::pieces::{BISHOP_VALUE, KNIGHT_VALUE, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE};
+
+/// Bonus for attacking an enemy piece that nothing defends.
+const HANGING_PIECE_BONUS: i32 = 40;
+/// Bonus for attacking an enemy piece with a less valuable piece.
+const WEAKER_ATTACKER_BONUS: i32 = 25;
+
+/// Bonus for keeping both bishops, which together cover squares of both colours.
+const BISHOP_PAIR_BONUS: i32 = 30;
+
+/// Bonus for a rook on a file with no pawns at all.
+const ROOK_OPEN_FILE_BONUS: i32 = 25;
+/// Bonus for a rook on a file with only enemy pawns.
+const ROOK_HALF_OPEN_FILE_BONUS: i32 = 10;
+
+/// Bonus for a knight on an outpost: a square in the enemy half, guarded by a pawn of its own
+/// side, that no enemy pawn can ever attack.
+const KNIGHT_OUTPOST_BONUS: i32 = 20;
+
+/// Penalty for a king shut in on its back rank by its own pawns while an enemy rook or queen
+/// can reach that rank.
+const BACK_RANK_PENALTY: i32 = 40;
+
+/// Bonus for winning the race in a king and pawn ending: a passed pawn the enemy king can't
+/// catch, which queens before anything the other side can push.
+const PAWN_RACE_BONUS: i32 = 600;
+
+/// Bonus per step the bare king is pushed away from the centre in a basic mating ending.
+const MOP_UP_EDGE_BONUS: i32 = 10;
+/// Bonus per step the attacking king closes in on the bare king.
+const MOP_UP_PROXIMITY_BONUS: i32 = 4;
+
+/// Game phase with all the starting pieces on the board; zero is a bare pawn ending.
+pub const OPENING_PHASE: i32 = 256;
+/// Phase weight of each knight, bishop, rook and queen, adding up to 24 for the starting set.
+const PHASE_WEIGHTS: [(PieceKind, i32); 4] =
+    [(PieceKind::Knight, 1), (PieceKind::Bishop, 1), (PieceKind::Rook, 2), (PieceKind::Queen, 4)];
+const STARTING_PHASE_WEIGHT: i32 = 24;
+
+/// Number of entries in an evaluation cache created with `EvalCache::default`.
+const DEFAULT_EVAL_CACHE_SIZE: usize = 1 << 16;
+
+/// A small cache of static evaluations keyed on the Zobrist hash. Each hash maps to a
+/// single slot; a different position landing in the same slot simply replaces it.
+pub struct EvalCache {
+    entries: Vec<Option<(u64, i32)>>, // (hash, score) per slot
+    pub hits: u64,
+}
+
+impl EvalCache {
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: vec![None; size.max(1)],
+            hits: 0,
+        }
+    }
+
+    /// Forget every cached evaluation.
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.hits = 0;
+    }
+}
+
+impl Default for EvalCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVAL_CACHE_SIZE)
+    }
+}
+
+/// Squares attacked by each piece, worked out once per `evaluate` call so that the terms
+/// needing them don't each walk the board again.
+pub struct AttackTable {
+    attacks: [BitBoard; TOTAL_SQUARES], // Indexed by the square the piece stands on
+}
+
+impl AttackTable {
+    pub fn new(board: &BoardState) -> Self {
+        let mut attacks = [BitBoard::empty(); TOTAL_SQUARES];
+        for square in board.all_pieces.iter() {
+            attacks[square.index()] = board.attacks_from(square.index());
+        }
+        Self { attacks }
+    }
+
+    /// Squares attacked by the piece on the square.
+    pub fn attacks_from(&self, square: usize) -> BitBoard {
+        self.attacks[square]
+    }
+
+    /// Pieces of the given colour attacking the square, as `BoardState::attackers_to` finds them.
+    pub fn attackers_to(&self, board: &BoardState, square: usize, by: PieceColour) -> BitBoard {
+        let pieces = match by {
+            PieceColour::White => board.all_white,
+            PieceColour::Black => board.all_black,
+        };
+        let mut attackers = BitBoard::empty();
+        for attacker in pieces.iter() {
+            if self.attacks[attacker.index()].is_set(square) {
+                attackers.set(attacker.index());
+            }
+        }
+        attackers
+    }
+}
+
+/// Static evaluation of the position in centipawns, from the point of view of the side to move:
+/// positive is good for the side to move. Negamax relies on this, negating the score of each
+/// child position to get it from the parent's side.
+pub fn evaluate(board: &BoardState) -> i32 {
+    if is_locked_pawn_position(board) {
+        return 0;
+    }
+
+    let table = AttackTable::new(board);
+    let score = material(board, PieceColour::White) - material(board, PieceColour::Black)
+        + bishop_pair(board, PieceColour::White)
+        - bishop_pair(board, PieceColour::Black)
+        + rook_files(board, PieceColour::White)
+        - rook_files(board, PieceColour::Black)
+        + knight_outposts(board, PieceColour::White)
+        - knight_outposts(board, PieceColour::Black)
+        + threats(board, &table, PieceColour::White)
+        - threats(board, &table, PieceColour::Black)
+        - back_rank_weakness(board, &table, PieceColour::White)
+        + back_rank_weakness(board, &table, PieceColour::Black)
+        + mop_up(board, PieceColour::White)
+        - mop_up(board, PieceColour::Black)
+        + pawn_race(board, PieceColour::White)
+        - pawn_race(board, PieceColour::Black);
+
+    match board.to_move {
+        PieceColour::White => score,
+        PieceColour::Black => -score,
+    }
+}
+
+/// Static evaluation as `evaluate` gives it, looking the position up in the cache by its hash
+/// first. A miss, or another position in the slot, evaluates afresh and fills the slot.
+pub fn evaluate_cached(board: &BoardState, cache: &mut EvalCache) -> i32 {
+    let hash = board.hash();
+    let slot = (hash % cache.entries.len() as u64) as usize;
+    if let Some((cached_hash, score)) = cache.entries[slot] {
+        if cached_hash == hash {
+            cache.hits += 1;
+            return score;
+        }
+    }
+
+    let score = evaluate(board);
+    cache.entries[slot] = Some((hash, score));
+    score
+}
+
+impl BoardState {
+    /// How far the game is from the endgame, from the knights, bishops, rooks and queens left:
+    /// `OPENING_PHASE` with the full starting set, falling to 0 once only kings and pawns
+    /// remain. Promoted pieces can't push it past the opening value. Meant for tapering
+    /// between middlegame and endgame scores.
+    pub fn game_phase(&self) -> i32 {
+        let weight: i32 = PHASE_WEIGHTS
+            .iter()
+            .map(|&(kind, weight)| {
+                let count = self.piece_bitboard(kind, PieceColour::White).count()
+                    + self.piece_bitboard(kind, PieceColour::Black).count();
+                count as i32 * weight
+            })
+            .sum();
+        weight.min(STARTING_PHASE_WEIGHT) * OPENING_PHASE / STARTING_PHASE_WEIGHT
+    }
+
+    /// Every legal move with the static eval of the position it leads to, from the mover's
+    /// point of view, best first. A one-ply hint for browsing moves, not a search: a move that
+    /// hangs a piece can still look good.
+    pub fn evaluate_moves(&mut self) -> Vec<(ChessMove, i32)> {
+        let mut scored: Vec<(ChessMove, i32)> = self
+            .generate_legal_moves()
+            .into_iter()
+            .map(|m| {
+                let undo = self.make_move(m);
+                let score = -evaluate(self);
+                self.unmake_move(undo);
+                (m, score)
+            })
+            .collect();
+        scored.sort_by_key(|&(_, score)| Reverse(score));
+        scored
+    }
+}
+
+/// Recognise a dead position where only kings and pawns remain, every pawn is blocked head-on
+/// by an enemy pawn and nothing can be captured. This is deliberately narrow: it ignores
+/// whether a king could walk round the chain later.
+pub fn is_locked_pawn_position(board: &BoardState) -> bool {
+    let (white_pawns, black_pawns) = (board.white_pawns.0, board.black_pawns.0);
+    let kings_and_pawns = white_pawns | black_pawns | board.white_king.0 | board.black_king.0;
+
+    white_pawns != 0
+        && board.all_pieces.0 == kings_and_pawns
+        && white_pawns << 8 == black_pawns
+        && board.black_pawns.iter().all(|square| !board.is_square_attacked(square.index(), PieceColour::White))
+        && board.white_pawns.iter().all(|square| !board.is_square_attacked(square.index(), PieceColour::Black))
+}
+
+/// Total material value for one side, not counting the king.
+pub fn material(board: &BoardState, colour: PieceColour) -> i32 {
+    let (pawns, knights, bishops, rooks, queens) = match colour {
+        PieceColour::White => (
+            board.white_pawns,
+            board.white_knights,
+            board.white_bishops,
+            board.white_rooks,
+            board.white_queens,
+        ),
+        PieceColour::Black => (
+            board.black_pawns,
+            board.black_knights,
+            board.black_bishops,
+            board.black_rooks,
+            board.black_queens,
+        ),
+    };
+
+    pawns.count() as i32 * PieceKind::Pawn.value()
+        + knights.count() as i32 * PieceKind::Knight.value()
+        + bishops.count() as i32 * PieceKind::Bishop.value()
+        + rooks.count() as i32 * PieceKind::Rook.value()
+        + queens.count() as i32 * PieceKind::Queen.value()
+}
+
+/// Bonus for a side that still has two or more bishops.
+pub fn bishop_pair(board: &BoardState, colour: PieceColour) -> i32 {
+    if board.piece_bitboard(PieceKind::Bishop, colour).count() >= 2 {
+        BISHOP_PAIR_BONUS
+    } else {
+        0
+    }
+}
+
+/// Reward rooks on files free of their own pawns, where they can use the whole file.
+pub fn rook_files(board: &BoardState, colour: PieceColour) -> i32 {
+    let own_pawns = board.piece_bitboard(PieceKind::Pawn, colour).0;
+    let enemy_pawns = board.piece_bitboard(PieceKind::Pawn, colour.opposite()).0;
+
+    board
+        .piece_bitboard(PieceKind::Rook, colour)
+        .iter()
+        .map(|square| {
+            let file = FILE_A << (square.index() % 8);
+            if own_pawns & file != 0 {
+                0
+            } else if enemy_pawns & file != 0 {
+                ROOK_HALF_OPEN_FILE_BONUS
+            } else {
+                ROOK_OPEN_FILE_BONUS
+            }
+        })
+        .sum()
+}
+
+/// Reward knights on outposts on the fourth to sixth rank, counted from the knight's side:
+/// squares a friendly pawn defends and no enemy pawn attacks now or could after advancing.
+pub fn knight_outposts(board: &BoardState, colour: PieceColour) -> i32 {
+    let enemy = colour.opposite();
+    let backward = -(colour.pawn_push_offset() as i8);
+
+    // Slide the enemy pawns down the board to cover every square they could advance to
+    let mut enemy_pawn_span = board.piece_bitboard(PieceKind::Pawn, enemy);
+    for _ in 0..5 {
+        enemy_pawn_span |= enemy_pawn_span.shift(backward).0;
+    }
+    let enemy_reach = pawn_attacks(enemy_pawn_span, enemy);
+    let defended = pawn_attacks(board.piece_bitboard(PieceKind::Pawn, colour), colour);
+    let outpost_ranks = match colour {
+        PieceColour::White => 3..=5,
+        PieceColour::Black => 2..=4,
+    };
+
+    let outposts = board
+        .piece_bitboard(PieceKind::Knight, colour)
+        .iter()
+        .map(Square::index)
+        .filter(|&square| outpost_ranks.contains(&(square / 8)))
+        .filter(|&square| defended.is_set(square) && !enemy_reach.is_set(square))
+        .count();
+    outposts as i32 * KNIGHT_OUTPOST_BONUS
+}
+
+/// Reward the given side for attacking enemy pieces that are undefended or worth more than
+/// their cheapest attacker.
+pub fn threats(board: &BoardState, table: &AttackTable, by: PieceColour) -> i32 {
+    let enemies = match by {
+        PieceColour::White => board.all_black,
+        PieceColour::Black => board.all_white,
+    };
+
+    let mut score = 0;
+    for square in enemies.iter().map(Square::index) {
+        let Some(victim) = board.piece_at(square) else {
+            continue;
+        };
+        if victim.kind == PieceKind::King {
+            continue;
+        }
+
+        let attackers = table.attackers_to(board, square, by);
+        let Some(cheapest_attacker) = attackers
+            .iter()
+            .map(Square::index)
+            .filter_map(|attacker| board.piece_at(attacker))
+            .map(|piece| piece.kind.value())
+            .min()
+        else {
+            continue;
+        };
+
+        if table.attackers_to(board, square, victim.colour).0 == 0 {
+            score += HANGING_PIECE_BONUS;
+        } else if cheapest_attacker < victim.kind.value() {
+            score += WEAKER_ATTACKER_BONUS;
+        }
+    }
+    score
+}
+
+/// Penalty for a king on its back rank with no luft: every square in front of it holds one of
+/// its own pawns, so a rook or queen landing on the rank could mate. Only counted when an
+/// enemy rook or queen already attacks some square of that rank.
+pub fn back_rank_weakness(board: &BoardState, table: &AttackTable, colour: PieceColour) -> i32 {
+    let back_rank = match colour {
+        PieceColour::White => 0,
+        PieceColour::Black => 7,
+    };
+    let king = board.king_square(colour);
+    if king / 8 != back_rank {
+        return 0;
+    }
+
+    let forward = colour.pawn_push_offset().signum() as i8;
+    let own_pawns = board.piece_bitboard(PieceKind::Pawn, colour);
+    let no_luft = [-1, 0, 1]
+        .into_iter()
+        .filter_map(|file_delta| step(king, forward, file_delta))
+        .all(|square| own_pawns.is_set(square));
+    if !no_luft {
+        return 0;
+    }
+
+    let rank_mask = 0xFF << (8 * back_rank);
+    let heavy_pieces = board.piece_bitboard(PieceKind::Rook, colour.opposite()).0
+        | board.piece_bitboard(PieceKind::Queen, colour.opposite()).0;
+    let reaches_rank = BitBoard(heavy_pieces)
+        .iter()
+        .any(|square| table.attacks_from(square.index()).0 & rank_mask != 0);
+    if reaches_rank {
+        BACK_RANK_PENALTY
+    } else {
+        0
+    }
+}
+
+/// In basic won endings (king and queen or rook against a bare king) reward the stronger
+/// side for driving the enemy king to the edge and bringing its own king closer, which is
+/// what it takes to deliver mate.
+pub fn mop_up(board: &BoardState, strong: PieceColour) -> i32 {
+    let (weak_pieces, weak_king, heavy_pieces) = match strong {
+        PieceColour::White => (
+            board.all_black,
+            board.black_king,
+            board.white_queens.0 | board.white_rooks.0,
+        ),
+        PieceColour::Black => (
+            board.all_white,
+            board.white_king,
+            board.black_queens.0 | board.black_rooks.0,
+        ),
+    };
+    if weak_pieces != weak_king || heavy_pieces == 0 {
+        return 0;
+    }
+
+    let weak_square = board.king_square(strong.opposite());
+    let strong_square = board.king_square(strong);
+    let (weak_rank, weak_file) = ((weak_square / 8) as i32, (weak_square % 8) as i32);
+    let (strong_rank, strong_file) = ((strong_square / 8) as i32, (strong_square % 8) as i32);
+
+    let centre_distance = (3 - weak_file).max(weak_file - 4) + (3 - weak_rank).max(weak_rank - 4);
+    let king_distance = (weak_file - strong_file).abs() + (weak_rank - strong_rank).abs();
+
+    MOP_UP_EDGE_BONUS * centre_distance + MOP_UP_PROXIMITY_BONUS * (14 - king_distance)
+}
+
+/// With only kings and pawns left, reward the side whose unstoppable pawn queens first. Each
+/// side's queening move is counted in plies from now, so on equal moves the side to move wins.
+pub fn pawn_race(board: &BoardState, colour: PieceColour) -> i32 {
+    let kings_and_pawns = board.white_pawns.0 | board.black_pawns.0 | board.white_king.0 | board.black_king.0;
+    if board.all_pieces.0 != kings_and_pawns {
+        return 0;
+    }
+    let Some(moves) = unstoppable_pawn_moves(board, colour) else {
+        return 0;
+    };
+
+    let queening_ply = |side: PieceColour, moves: u32| 2 * moves - u32::from(board.to_move == side);
+    let ours = queening_ply(colour, moves);
+    let theirs = unstoppable_pawn_moves(board, colour.opposite()).map(|moves| queening_ply(colour.opposite(), moves));
+    if theirs.is_none_or(|theirs| ours < theirs) {
+        PAWN_RACE_BONUS
+    } else {
+        0
+    }
+}
+
+/// Moves the side needs to queen its fastest pawn that is passed, has a clear path and
+/// outruns the enemy king by the rule of the square, or `None` if it has no such pawn.
+fn unstoppable_pawn_moves(board: &BoardState, colour: PieceColour) -> Option<u32> {
+    let enemy = colour.opposite();
+    let enemy_pawns = board.piece_bitboard(PieceKind::Pawn, enemy).0;
+    let enemy_king = board.king_square(enemy);
+    // The defending king takes the first step when it is its move
+    let tempo = u32::from(board.to_move == enemy);
+    let promotion_rank = colour.promotion_rank();
+
+    board
+        .piece_bitboard(PieceKind::Pawn, colour)
+        .iter()
+        .map(Square::index)
+        .filter_map(|square| {
+            let file = square % 8;
+            let ahead = match colour {
+                PieceColour::White => u64::MAX << square << 1,
+                PieceColour::Black => (1 << square) - 1,
+            };
+            let path = (FILE_A << file) & ahead;
+            let span = path | BitBoard(path).shift(1).0 | BitBoard(path).shift(-1).0;
+            if enemy_pawns & span != 0 || board.all_pieces.0 & path != 0 {
+                return None;
+            }
+
+            let double_push = u32::from(colour.starting_rank_range().contains(&square));
+            let moves = promotion_rank.abs_diff(square / 8) as u32 - double_push;
+            let king_moves = (enemy_king / 8).abs_diff(promotion_rank).max((enemy_king % 8).abs_diff(file)) as u32;
+            (king_moves > moves + tempo).then_some(moves)
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_position_is_balanced() {
+        let board = BoardState::new();
+        assert_eq!(evaluate(&board), 0);
+    }
+
+    #[test]
+    fn test_threat_on_undefended_rook() {
+        // The c3 knight attacks the loose b5 rook; with the rook on h5 nothing is attacked
+        let threatened = BoardState::from_fen("4k3/8/8/1r6/8/2N5/8/4K3 w - - 0 1").unwrap();
+        let quiet = BoardState::from_fen("4k3/8/8/7r/8/2N5/8/4K3 w - - 0 1").unwrap();
+
+        assert!(threats(&threatened, &AttackTable::new(&threatened), PieceColour::White) > 0);
+        assert_eq!(threats(&quiet, &AttackTable::new(&quiet), PieceColour::White), 0);
+        assert!(evaluate(&threatened) > evaluate(&quiet));
+    }
+
+    #[test]
+    fn test_locked_pawn_chain_is_drawn() {
+        let locked = BoardState::from_fen("4k3/8/8/1p1p1p1p/1P1P1P1P/8/8/4K3 w - - 0 1").unwrap();
+        assert!(is_locked_pawn_position(&locked));
+        assert_eq!(evaluate(&locked), 0);
+
+        // A pawn that can still capture, or one with a free path, leaves the game open
+        let capture = BoardState::from_fen("4k3/8/8/1p1p1p1p/1P1PP2P/8/8/4K3 w - - 0 1").unwrap();
+        let free = BoardState::from_fen("4k3/8/8/1p1p4/1P1P3P/8/8/4K3 w - - 0 1").unwrap();
+        assert!(!is_locked_pawn_position(&capture));
+        assert!(!is_locked_pawn_position(&free));
+    }
+
+    #[test]
+    fn test_attack_table_matches_recomputed_attacks() {
+        let board =
+            BoardState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let table = AttackTable::new(&board);
+
+        for square in 0..64 {
+            assert_eq!(table.attacks_from(square), board.attacks_from(square));
+        }
+        for square in 0..64 {
+            assert_eq!(
+                table.attackers_to(&board, square, PieceColour::Black),
+                board.attackers_to(square, PieceColour::Black)
+            );
+        }
+    }
+
+    #[test]
+    fn test_bishop_pair() {
+        let bishops = BoardState::from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+        let knights = BoardState::from_fen("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1").unwrap();
+
+        assert_eq!(bishop_pair(&bishops, PieceColour::White), BISHOP_PAIR_BONUS);
+        assert_eq!(bishop_pair(&knights, PieceColour::White), 0);
+        assert_eq!(
+            evaluate(&bishops) - evaluate(&knights),
+            2 * (BISHOP_VALUE - KNIGHT_VALUE) + BISHOP_PAIR_BONUS
+        );
+    }
+
+    #[test]
+    fn test_rook_on_open_file() {
+        // The same rook and pawns, with the rook on the open d-file or behind its own e-pawn
+        let open = BoardState::from_fen("k7/8/8/8/8/8/4P3/3R2K1 w - - 0 1").unwrap();
+        let blocked = BoardState::from_fen("k7/8/8/8/8/8/4P3/4R1K1 w - - 0 1").unwrap();
+        let half_open = BoardState::from_fen("k7/3p4/8/8/8/8/4P3/3R2K1 w - - 0 1").unwrap();
+
+        assert_eq!(rook_files(&open, PieceColour::White), ROOK_OPEN_FILE_BONUS);
+        assert_eq!(rook_files(&blocked, PieceColour::White), 0);
+        assert_eq!(rook_files(&half_open, PieceColour::White), ROOK_HALF_OPEN_FILE_BONUS);
+        assert!(evaluate(&open) > evaluate(&blocked));
+    }
+
+    #[test]
+    fn test_knight_outpost() {
+        // The c4 pawn guards d5, and black has no c- or e-pawn left to chase the knight away
+        let outpost = BoardState::from_fen("6k1/p7/8/3N4/2P5/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(knight_outposts(&outpost, PieceColour::White), KNIGHT_OUTPOST_BONUS);
+        // On d3 the knight has as many squares but sits in its own half
+        let home = BoardState::from_fen("6k1/p7/8/8/2P5/3N4/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(knight_outposts(&home, PieceColour::White), 0);
+        assert_eq!(evaluate(&outpost) - evaluate(&home), KNIGHT_OUTPOST_BONUS);
+
+        // An e7 pawn could still come to e6, and without c4 nothing defends the knight
+        let chased = BoardState::from_fen("6k1/p3p3/8/3N4/2P5/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(knight_outposts(&chased, PieceColour::White), 0);
+        let undefended = BoardState::from_fen("6k1/p7/8/3N4/8/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(knight_outposts(&undefended, PieceColour::White), 0);
+
+        // The same outpost for black, on d4 guarded from e5
+        let black = BoardState::from_fen("6k1/8/8/4p3/3n4/8/P7/6K1 b - - 0 1").unwrap();
+        assert_eq!(knight_outposts(&black, PieceColour::Black), KNIGHT_OUTPOST_BONUS);
+    }
+
+    #[test]
+    fn test_back_rank_weakness() {
+        // The e8 rook sweeps down the open e-file onto the first rank, behind f2, g2 and h2
+        let trapped = BoardState::from_fen("4r1k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let table = AttackTable::new(&trapped);
+        assert_eq!(back_rank_weakness(&trapped, &table, PieceColour::White), BACK_RANK_PENALTY);
+        // Black's king has no luft either, but no white rook or queen to fear
+        assert_eq!(back_rank_weakness(&trapped, &table, PieceColour::Black), 0);
+
+        // With h3 played the king can breathe
+        let luft = BoardState::from_fen("4r1k1/5ppp/8/8/8/7P/5PP1/6K1 w - - 0 1").unwrap();
+        assert_eq!(back_rank_weakness(&luft, &AttackTable::new(&luft), PieceColour::White), 0);
+        assert!(evaluate(&trapped) < evaluate(&luft));
+
+        // A white piece on the e-file keeps the rook off the first rank
+        let blocked = BoardState::from_fen("4r1k1/5ppp/8/8/8/8/4BPPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(back_rank_weakness(&blocked, &AttackTable::new(&blocked), PieceColour::White), 0);
+    }
+
+    #[test]
+    fn test_mop_up_prefers_cornered_king() {
+        let cornered = BoardState::from_fen("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap();
+        let central = BoardState::from_fen("8/8/1K6/8/3k4/8/7Q/8 w - - 0 1").unwrap();
+
+        assert!(mop_up(&cornered, PieceColour::White) > mop_up(&central, PieceColour::White));
+        assert_eq!(mop_up(&cornered, PieceColour::Black), 0);
+
+        // Only applies once the defender is down to a bare king
+        assert_eq!(mop_up(&BoardState::new(), PieceColour::White), 0);
+    }
+
+    #[test]
+    fn test_game_phase() {
+        assert_eq!(BoardState::new().game_phase(), OPENING_PHASE);
+        let bare_kings = BoardState::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(bare_kings.game_phase(), 0);
+        let pawns_only = BoardState::from_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(pawns_only.game_phase(), 0);
+
+        // Without the queens, 16 of the 24 weight points are left
+        let queenless = BoardState::from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1").unwrap();
+        assert_eq!(queenless.game_phase(), OPENING_PHASE * 16 / 24);
+
+        // Extra queens from promotion don't go past the opening
+        let promoted = BoardState::from_fen("qnbqkbnr/1ppppppp/8/8/8/8/1PPPPPPP/QNBQKBNR w Kk - 0 1").unwrap();
+        assert_eq!(promoted.game_phase(), OPENING_PHASE);
+    }
+
+    #[test]
+    fn test_pawn_race() {
+        // The b5 and g4 pawns both need three moves, with each king far outside the other's square
+        let mut race = BoardState::from_fen("7K/8/8/1P6/6p1/8/8/k7 w - - 0 1").unwrap();
+        assert_eq!(pawn_race(&race, PieceColour::White), PAWN_RACE_BONUS);
+        assert_eq!(pawn_race(&race, PieceColour::Black), 0);
+        assert!(evaluate(&race) > 0);
+
+        // With black to move the g-pawn gets there first
+        race.to_move = PieceColour::Black;
+        assert_eq!(pawn_race(&race, PieceColour::White), 0);
+        assert_eq!(pawn_race(&race, PieceColour::Black), PAWN_RACE_BONUS);
+        assert!(evaluate(&race) > 0);
+
+        // A move ahead, b6 wins the race whoever is to move
+        let ahead = BoardState::from_fen("7K/8/1P6/8/6p1/8/8/k7 b - - 0 1").unwrap();
+        assert_eq!(pawn_race(&ahead, PieceColour::White), PAWN_RACE_BONUS);
+        assert_eq!(pawn_race(&ahead, PieceColour::Black), 0);
+
+        // Rule of the square: from d8 the king catches the a5 pawn, from e8 it can't
+        let caught = BoardState::from_fen("3k4/8/8/P7/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(pawn_race(&caught, PieceColour::White), 0);
+        let outrun = BoardState::from_fen("4k3/8/8/P7/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(pawn_race(&outrun, PieceColour::White), PAWN_RACE_BONUS);
+        // ... unless it is black's move, which brings e8 back into the square
+        let defender_to_move = BoardState::from_fen("4k3/8/8/P7/8/8/8/7K b - - 0 1").unwrap();
+        assert_eq!(pawn_race(&defender_to_move, PieceColour::White), 0);
+
+        // A pawn on its starting rank can push two squares, just enough to outrun the g8 king
+        let start = BoardState::from_fen("6k1/8/8/8/8/8/P7/7K w - - 0 1").unwrap();
+        assert_eq!(pawn_race(&start, PieceColour::White), PAWN_RACE_BONUS);
+    }
+
+    #[test]
+    fn test_eval_cache_hit() {
+        let board = BoardState::from_fen("4k3/8/8/1r6/8/2N5/8/4K3 w - - 0 1").unwrap();
+        let mut cache = EvalCache::default();
+
+        assert_eq!(evaluate_cached(&board, &mut cache), evaluate(&board));
+        assert_eq!(cache.hits, 0);
+
+        assert_eq!(evaluate_cached(&board, &mut cache), evaluate(&board));
+        assert_eq!(cache.hits, 1);
+
+        // A different position in the same slot is evaluated afresh
+        let start = BoardState::new();
+        let mut tiny = EvalCache::new(1);
+        evaluate_cached(&board, &mut tiny);
+        assert_eq!(evaluate_cached(&start, &mut tiny), evaluate(&start));
+        assert_eq!(tiny.hits, 0);
+    }
+
+    #[test]
+    fn test_material_advantage() {
+        // White is a rook up, and the rook stands on the open a-file. Against the bare king the
+        // mop-up term counts as well
+        let board = BoardState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+
+        assert_eq!(material(&board, PieceColour::White), ROOK_VALUE);
+        let mop_up_bonus = mop_up(&board, PieceColour::White);
+        assert_eq!(evaluate(&board), ROOK_VALUE + ROOK_OPEN_FILE_BONUS + mop_up_bonus);
+    }
+
+    #[test]
+    fn test_score_is_relative_to_side_to_move() {
+        // White is a rook up, whoever is to move
+        let mut board = BoardState::from_fen("4k3/4p3/8/8/8/8/4P3/R3K3 w - - 0 1").unwrap();
+        let white_to_move = evaluate(&board);
+        assert!(white_to_move > 0);
+
+        board.to_move = PieceColour::Black;
+        assert!(evaluate(&board) < 0);
+        assert_eq!(evaluate(&board), -white_to_move);
+    }
+
+    #[test]
+    fn test_evaluate_moves() {
+        // Black's queen hangs to the d1 rook
+        let mut board = BoardState::from_fen("r3k3/8/8/3q4/8/8/PP6/3RK3 w - - 0 1").unwrap();
+        let scored = board.evaluate_moves();
+
+        let mut moves: Vec<ChessMove> = scored.iter().map(|&(m, _)| m).collect();
+        let mut legal = board.generate_legal_moves();
+        moves.sort_by_key(|m| (m.from, m.to));
+        legal.sort_by_key(|m| (m.from, m.to));
+        assert_eq!(moves, legal);
+
+        assert!(scored.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert_eq!(scored[0].0, ChessMove::from_uci("d1d5").unwrap());
+        assert!(scored[0].1 > 0);
+    }
+}
@@ -0,0 +1,255 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::board::{BoardState, BOARD_SIZE};
+use crate::pieces::{Piece, PieceColour, PieceKind};
+
+/// FEN string for the standard starting position.
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Errors that can occur while parsing a FEN string.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FenError {
+    MissingField,
+    InvalidPiece(char),
+    InvalidRank,
+    InvalidSideToMove,
+    InvalidCastling,
+    InvalidEnPassant,
+    InvalidClock,
+    InvalidKingCount,
+    OpponentInCheck,
+    PawnOnBackRank,
+}
+
+/// Squares on the first and eighth ranks, where no pawn can stand.
+pub(crate) const BACK_RANKS: u64 = 0xFF00_0000_0000_00FF;
+
+/// Parse an algebraic square such as "e3" into a square index (0-63).
+pub fn parse_square(text: &str) -> Option<usize> {
+    let mut chars = text.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+    if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+
+    Some((rank as usize - '1' as usize) * BOARD_SIZE + (file as usize - 'a' as usize))
+}
+
+/// Name a square index (0-63) in algebraic notation, such as "e3".
+pub fn square_name(square: usize) -> String {
+    let file = (b'a' + (square % BOARD_SIZE) as u8) as char;
+    let rank = (b'1' + (square / BOARD_SIZE) as u8) as char;
+    format!("{}{}", file, rank)
+}
+
+impl BoardState {
+    /// Build a board from a FEN string.
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or(FenError::MissingField)?;
+        let side = fields.next().ok_or(FenError::MissingField)?;
+        let castling = fields.next().ok_or(FenError::MissingField)?;
+        let en_passant = fields.next().ok_or(FenError::MissingField)?;
+
+        let mut board = BoardState::empty();
+
+        // Piece placement, from rank 8 down to rank 1
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != BOARD_SIZE {
+            return Err(FenError::InvalidRank);
+        }
+        for (i, rank_text) in ranks.iter().enumerate() {
+            let rank = BOARD_SIZE - 1 - i;
+            let mut file = 0;
+            for c in rank_text.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as usize;
+                } else {
+                    if file >= BOARD_SIZE {
+                        return Err(FenError::InvalidRank);
+                    }
+                    board.add_piece(rank * BOARD_SIZE + file, piece_from_char(c)?);
+                    file += 1;
+                }
+            }
+            if file != BOARD_SIZE {
+                return Err(FenError::InvalidRank);
+            }
+        }
+
+        board.to_move = match side {
+            "w" => PieceColour::White,
+            "b" => PieceColour::Black,
+            _ => return Err(FenError::InvalidSideToMove),
+        };
+
+        // Castling rights in the order [white kingside, white queenside, black kingside, black queenside]
+        if castling != "-" {
+            for c in castling.chars() {
+                let index = match c {
+                    'K' => 0,
+                    'Q' => 1,
+                    'k' => 2,
+                    'q' => 3,
+                    _ => return Err(FenError::InvalidCastling),
+                };
+                board.castling_rights[index] = true;
+            }
+        }
+
+        if en_passant != "-" {
+            let square = parse_square(en_passant).ok_or(FenError::InvalidEnPassant)?;
+            let expected_rank = match board.to_move {
+                PieceColour::White => 5,
+                PieceColour::Black => 2,
+            };
+            if square / BOARD_SIZE != expected_rank {
+                return Err(FenError::InvalidEnPassant);
+            }
+
+            // Only keep the square when it can actually be captured on, so that
+            // equivalent positions hash the same.
+            if board.en_passant_capturable(square, board.to_move) {
+                board.en_passant_square = Some(square);
+            }
+        }
+
+        // The clocks are optional, but must be numeric when present
+        if let Some(half_move_clock) = fields.next() {
+            board.half_move_clock = half_move_clock.parse().map_err(|_| FenError::InvalidClock)?;
+        }
+        if let Some(full_move_number) = fields.next() {
+            full_move_number.parse::<u32>().map_err(|_| FenError::InvalidClock)?;
+        }
+
+        // Play needs exactly one king per side
+        if board.white_king.count() != 1 || board.black_king.count() != 1 {
+            return Err(FenError::InvalidKingCount);
+        }
+
+        // Pawns promote on reaching the last rank and can never stand on their first
+        if (board.white_pawns.0 | board.black_pawns.0) & BACK_RANKS != 0 {
+            return Err(FenError::PawnOnBackRank);
+        }
+
+        // The side that just moved can't have left its own king in check
+        if board.is_in_check(board.to_move.opposite()) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        board.refresh_hash();
+        Ok(board)
+    }
+
+}
+
+fn piece_from_char(c: char) -> Result<Piece, FenError> {
+    let kind = match c.to_ascii_lowercase() {
+        'p' => PieceKind::Pawn,
+        'n' => PieceKind::Knight,
+        'b' => PieceKind::Bishop,
+        'r' => PieceKind::Rook,
+        'q' => PieceKind::Queen,
+        'k' => PieceKind::King,
+        _ => return Err(FenError::InvalidPiece(c)),
+    };
+    let colour = if c.is_ascii_uppercase() {
+        PieceColour::White
+    } else {
+        PieceColour::Black
+    };
+
+    Ok(Piece { kind, colour })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_fen_matches_new_board() {
+        let board = BoardState::from_fen(START_FEN).unwrap();
+        let start = BoardState::new();
+
+        assert_eq!(board.all_white, start.all_white);
+        assert_eq!(board.all_black, start.all_black);
+        assert_eq!(board.white_knights, start.white_knights);
+        assert_eq!(board.black_queens, start.black_queens);
+        assert_eq!(board.to_move, PieceColour::White);
+        assert_eq!(board.castling_rights, [true, true, true, true]);
+        assert_eq!(board.en_passant_square, None);
+    }
+
+    #[test]
+    fn test_square_names() {
+        for square in [0, 7, 20, 63] {
+            assert_eq!(parse_square(&square_name(square)), Some(square));
+        }
+        assert_eq!(square_name(20), "e3");
+    }
+
+    #[test]
+    fn test_en_passant_dropped_without_capturer() {
+        // After 1.e4 no black pawn stands next to e4, so e3 cannot be captured on
+        let board =
+            BoardState::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+
+        assert_eq!(board.en_passant_square, None);
+    }
+
+    #[test]
+    fn test_en_passant_kept_with_capturer() {
+        // Black pawn on d4 can capture e4 en passant
+        let board =
+            BoardState::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+
+        assert_eq!(board.en_passant_square, Some(20)); // e3
+    }
+
+    #[test]
+    fn test_invalid_fen() {
+        assert_eq!(BoardState::from_fen("8/8/8 w - -").unwrap_err(), FenError::InvalidRank);
+        assert_eq!(
+            BoardState::from_fen("8/8/8/8/8/8/8/8 x - -").unwrap_err(),
+            FenError::InvalidSideToMove
+        );
+    }
+
+    #[test]
+    fn test_opponent_in_check() {
+        // Black's king is attacked by the e1 rook with White to move
+        assert_eq!(
+            BoardState::from_fen("4k3/8/8/8/8/8/8/K3R3 w - - 0 1").unwrap_err(),
+            FenError::OpponentInCheck
+        );
+        assert!(BoardState::from_fen("4k3/8/8/8/8/8/8/K3R3 b - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn test_king_count() {
+        assert_eq!(
+            BoardState::from_fen("4k3/8/8/8/8/8/8/3KK3 w - - 0 1").unwrap_err(),
+            FenError::InvalidKingCount
+        );
+        assert_eq!(
+            BoardState::from_fen("8/8/8/8/8/8/8/4K3 w - - 0 1").unwrap_err(),
+            FenError::InvalidKingCount
+        );
+    }
+
+    #[test]
+    fn test_pawn_on_back_rank() {
+        assert_eq!(
+            BoardState::from_fen("4k2P/8/8/8/8/8/8/4K3 w - - 0 1").unwrap_err(),
+            FenError::PawnOnBackRank
+        );
+        assert_eq!(
+            BoardState::from_fen("4k3/8/8/8/8/8/8/p3K3 w - - 0 1").unwrap_err(),
+            FenError::PawnOnBackRank
+        );
+        assert!(BoardState::from_fen("4k3/P7/8/8/8/8/7p/4K3 w - - 0 1").is_ok());
+    }
+}
@@ -1,3 +1,380 @@
+use alloc::vec::Vec;
+
 use crate::board::BoardState;
-use crate::pieces::PieceColour;
+use crate::fen::FenError;
+use crate::history::{GameState, History};
+use crate::moves::{ChessMove, MoveError};
+use crate::pieces::{PieceColour, PieceKind};
+
+/// A game in progress, keeping the board and its history in step so that moves can be
+/// played, taken back and replayed, as a GUI's undo and redo buttons do.
+pub struct Game {
+    board: BoardState,
+    history: History,
+    redo_stack: Vec<ChessMove>, // Moves taken back with `undo`, most recent last
+    pending_promotion: Option<ChessMove>, // Pawn move waiting for `promote` to pick its piece
+}
+
+/// What became of a move handed to `Game::play`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MoveStatus {
+    Played,
+    /// A pawn move onto the last rank came without a promotion piece. It is held until
+    /// `Game::promote` is told which piece to make.
+    NeedsPromotion,
+}
+
+impl Game {
+    /// Start a new game from the initial position.
+    pub fn new() -> Self {
+        Self::from_board(BoardState::new())
+    }
+
+    /// Resume a game from the position in a FEN string. Nothing before it is known, so
+    /// repetitions are counted from this position on.
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        BoardState::from_fen(fen).map(Self::from_board)
+    }
+
+    /// Replay a game from the initial position, given its moves in UCI form. Fails with the
+    /// index of the first move that can't be read or played.
+    pub fn from_uci_moves(moves: &[&str]) -> Result<Self, MoveError> {
+        let mut game = Self::new();
+        for (index, text) in moves.iter().enumerate() {
+            let chess_move = ChessMove::from_uci(text).ok_or(MoveError::IllegalMoveAt(index))?;
+            match game.play(chess_move) {
+                Ok(MoveStatus::Played) => {}
+                _ => return Err(MoveError::IllegalMoveAt(index)),
+            }
+        }
+        Ok(game)
+    }
+
+    /// Start the history with the given position, so returning to it counts as a repetition.
+    fn from_board(board: BoardState) -> Self {
+        let mut history = History::starting_from(board.to_move);
+        history.push(GameState::from_position(board.hash(), board.half_move_clock));
+
+        Self {
+            board,
+            history,
+            redo_stack: Vec::new(),
+            pending_promotion: None,
+        }
+    }
+
+    pub fn board(&self) -> &BoardState {
+        &self.board
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Number of moves played since the starting position. The history holds one more state,
+    /// the starting position itself.
+    pub fn len(&self) -> usize {
+        self.history.len() - 1
+    }
+
+    /// Check if no moves have been played yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Zobrist hash of the current position.
+    pub fn hash(&self) -> u64 {
+        self.board.hash()
+    }
+
+    /// All legal moves for the side to move.
+    pub fn legal_moves(&mut self) -> Vec<ChessMove> {
+        self.board.generate_legal_moves()
+    }
+
+    /// Play a legal move. Any moves waiting to be redone are dropped, as the game has
+    /// branched away from them. A pawn move onto the last rank without a promotion piece is
+    /// not played but held for `promote`, rather than quietly becoming a queen.
+    pub fn play(&mut self, chess_move: ChessMove) -> Result<MoveStatus, MoveError> {
+        self.pending_promotion = None;
+        let legal = self.legal_moves();
+        let promotes = legal.contains(&ChessMove { promotion: Some(PieceKind::Queen), ..chess_move });
+        if chess_move.promotion.is_none() && promotes {
+            self.pending_promotion = Some(chess_move);
+            return Ok(MoveStatus::NeedsPromotion);
+        }
+        if !legal.contains(&chess_move) {
+            return Err(MoveError::IllegalMove);
+        }
+
+        self.push_move(chess_move);
+        self.redo_stack.clear();
+        Ok(MoveStatus::Played)
+    }
+
+    /// Finish the pawn move `play` held back, promoting to the given piece. Fails if no move
+    /// is waiting or the piece can't be promoted to; the move stays pending in that case.
+    pub fn promote(&mut self, kind: PieceKind) -> Result<(), MoveError> {
+        let pending = self.pending_promotion.ok_or(MoveError::IllegalMove)?;
+        let chess_move = ChessMove { promotion: Some(kind), ..pending };
+        if !self.legal_moves().contains(&chess_move) {
+            return Err(MoveError::IllegalMove);
+        }
+
+        self.play(chess_move).map(|_| ())
+    }
+
+    /// The pawn move waiting for a promotion piece, if any.
+    pub fn pending_promotion(&self) -> Option<ChessMove> {
+        self.pending_promotion
+    }
+
+    /// Take back the last move, returning it.
+    pub fn undo(&mut self) -> Option<ChessMove> {
+        self.pending_promotion = None;
+        let chess_move = self.board.undo_last()?;
+        self.history.pop();
+        self.redo_stack.push(chess_move);
+        Some(chess_move)
+    }
+
+    /// Replay the last move taken back with `undo`, returning it.
+    pub fn redo(&mut self) -> Option<ChessMove> {
+        self.pending_promotion = None;
+        let chess_move = self.redo_stack.pop()?;
+        self.push_move(chess_move);
+        Some(chess_move)
+    }
+
+    /// Play a move known to be legal and record the resulting position.
+    fn push_move(&mut self, chess_move: ChessMove) {
+        self.board.apply_move(chess_move);
+        let state = GameState::from_position(self.hash(), self.board.half_move_clock);
+        self.history.push(state);
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a game ended without either player resigning or claiming a draw.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    Checkmate { winner: PieceColour },
+    Stalemate,
+    DrawInsufficientMaterial,
+    DrawFivefoldRepetition,
+    DrawSeventyFiveMoveRule,
+}
+
+impl BoardState {
+    /// The result if the game is over, or `None` while it goes on.
+    pub fn game_result(&self, history: &History) -> Option<GameResult> {
+        // Bare kings can't be settled any other way, so skip move generation
+        if self.all_pieces.0 == self.white_king.0 | self.black_king.0 {
+            return Some(GameResult::DrawInsufficientMaterial);
+        }
+
+        if !self.clone().has_legal_move() {
+            return Some(if self.side_to_move_in_check() {
+                GameResult::Checkmate { winner: self.to_move.opposite() }
+            } else {
+                GameResult::Stalemate
+            });
+        }
+
+        if history.is_empty() {
+            None
+        } else if history.is_fivefold_repetition() {
+            Some(GameResult::DrawFivefoldRepetition)
+        } else if history.is_seventy_five_move_rule() {
+            Some(GameResult::DrawSeventyFiveMoveRule)
+        } else {
+            None
+        }
+    }
+
+    /// Check if the side to move may claim a draw by threefold repetition of the current
+    /// position, the last one in the history, or the fifty-move rule.
+    pub fn can_claim_draw(&self, history: &History) -> bool {
+        let Some(current) = history.iter().last() else {
+            return false;
+        };
+        history.repetition_count(current.zobrist_hash()) >= 3 || history.is_fifty_move_rule()
+    }
+
+    /// Check if the game is drawn without either player claiming it, by fivefold
+    /// repetition or the seventy-five-move rule.
+    pub fn is_automatic_draw(&self, history: &History) -> bool {
+        if history.is_empty() {
+            return false;
+        }
+
+        // A checkmate on the final move takes precedence over the seventy-five-move rule
+        history.is_fivefold_repetition() || (history.is_seventy_five_move_rule() && !self.is_checkmate())
+    }
+
+    /// Check if the side to move is checkmated.
+    fn is_checkmate(&self) -> bool {
+        self.side_to_move_in_check() && !self.clone().has_legal_move()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::zorbist::ZobristHashing;
+    use super::*;
+
+    #[test]
+    fn test_threefold_is_claimable_not_automatic() {
+        let board = BoardState::new();
+        let mut history = History::new();
+
+        // Shuffle between positions A and B, each ply reversible
+        let shuffle = |clock: u16| GameState::from_position(if clock.is_multiple_of(2) { 12345 } else { 67890 }, clock);
+
+        for clock in 0..=4 {
+            history.push(shuffle(clock));
+        }
+        assert_eq!(history.repetition_count(12345), 3);
+        assert!(board.can_claim_draw(&history));
+        assert!(!board.is_automatic_draw(&history));
+
+        for clock in 5..=8 {
+            history.push(shuffle(clock));
+        }
+        assert_eq!(history.repetition_count(12345), 5);
+        assert!(board.is_automatic_draw(&history));
+    }
+
+    #[test]
+    fn test_move_rules() {
+        let board = BoardState::new();
+        let mut history = History::new();
+        assert!(!board.can_claim_draw(&history));
+        assert!(!board.is_automatic_draw(&history));
+
+        history.push(GameState::from_position(1, 100));
+        assert!(board.can_claim_draw(&history));
+        assert!(!board.is_automatic_draw(&history));
+
+        history.push(GameState::from_position(2, 150));
+        assert!(board.is_automatic_draw(&history));
+
+        // Unless the final move delivered mate
+        let mut mated = BoardState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        mated.make_move(ChessMove { from: 0, to: 56, promotion: None });
+        assert!(!mated.is_automatic_draw(&history));
+    }
+
+    #[test]
+    fn test_game_result() {
+        let history = History::new();
+        let bare_kings = BoardState::from_fen("8/8/3k4/8/8/4K3/8/8 w - - 0 1").unwrap();
+        assert_eq!(bare_kings.game_result(&history), Some(GameResult::DrawInsufficientMaterial));
+
+        assert_eq!(BoardState::new().game_result(&history), None);
+
+        let mated = BoardState::from_fen("k7/2K5/8/8/8/8/8/Q7 b - - 0 1").unwrap();
+        assert_eq!(
+            mated.game_result(&history),
+            Some(GameResult::Checkmate { winner: PieceColour::White })
+        );
+
+        let stalemate = BoardState::from_fen("k7/8/1Q6/8/8/8/8/7K b - - 0 1").unwrap();
+        assert_eq!(stalemate.game_result(&history), Some(GameResult::Stalemate));
+    }
+
+    #[test]
+    fn test_game_undo_redo() {
+        let mut game = Game::new();
+        game.play(ChessMove::from_uci("e2e4").unwrap()).unwrap();
+        game.play(ChessMove::from_uci("e7e5").unwrap()).unwrap();
+        let hash = game.hash();
+
+        assert_eq!(game.undo(), ChessMove::from_uci("e7e5"));
+        assert_eq!(game.history().len(), 2);
+        assert_ne!(game.hash(), hash);
+
+        assert_eq!(game.redo(), ChessMove::from_uci("e7e5"));
+        assert_eq!(game.hash(), hash);
+        assert_eq!(game.history().len(), 3);
+        assert_eq!(game.board().to_move, PieceColour::White);
+        assert_eq!(game.redo(), None);
+
+        // A new move drops whatever was left to redo
+        game.undo();
+        game.play(ChessMove::from_uci("c7c5").unwrap()).unwrap();
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.play(ChessMove::from_uci("e1e3").unwrap()), Err(MoveError::IllegalMove));
+        assert_eq!(game.legal_moves().len(), 30);
+    }
+
+    #[test]
+    fn test_game_from_fen() {
+        let fen = "4k1n1/8/8/8/8/8/8/1N2K3 w - - 12 40";
+        let mut game = Game::from_fen(fen).unwrap();
+        let hash = ZobristHashing::new().compute_hash(&BoardState::from_fen(fen).unwrap());
+
+        assert_eq!(game.hash(), hash);
+        assert_eq!(game.history().len(), 1);
+        assert_eq!(game.history().repetition_count(hash), 1);
+        assert!(Game::from_fen("not a fen").is_err());
+
+        // Two round trips of the knights bring the loaded position up for the third time
+        for _ in 0..2 {
+            for text in ["b1c3", "g8f6", "c3b1", "f6g8"] {
+                assert!(!game.board().can_claim_draw(game.history()));
+                game.play(ChessMove::from_uci(text).unwrap()).unwrap();
+            }
+        }
+        assert_eq!(game.hash(), hash);
+        assert_eq!(game.history().repetition_count(hash), 3);
+        assert!(game.board().can_claim_draw(game.history()));
+    }
+
+    #[test]
+    fn test_game_from_uci_moves() {
+        let game = Game::from_uci_moves(&["e2e4", "e7e5", "g1f3"]).unwrap();
+
+        // Three moves, with the starting position as well in the history
+        assert_eq!(game.len(), 3);
+        assert_eq!(game.history().len(), 4);
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        assert_eq!(game.hash(), Game::from_fen(fen).unwrap().hash());
+
+        assert_eq!(Game::from_uci_moves(&["e2e4", "e2e4"]).err(), Some(MoveError::IllegalMoveAt(1)));
+        assert_eq!(Game::from_uci_moves(&["e2e4", "xyz"]).err(), Some(MoveError::IllegalMoveAt(1)));
+        // A pawn reaching the last rank must name its piece
+        let unpromoted = ["a2a4", "b7b5", "a4b5", "a7a6", "b5a6", "c8b7", "a6a7", "e7e6", "a7b8"];
+        assert_eq!(Game::from_uci_moves(&unpromoted).err(), Some(MoveError::IllegalMoveAt(8)));
+    }
+
+    #[test]
+    fn test_promotion_needs_a_piece() {
+        let mut game = Game::from_fen("8/4P3/8/8/8/k7/8/4K3 w - - 0 1").unwrap();
+        let hash = game.hash();
+
+        let push = ChessMove::from_uci("e7e8").unwrap();
+        assert_eq!(game.play(push), Ok(MoveStatus::NeedsPromotion));
+        assert_eq!(game.pending_promotion(), Some(push));
+        assert_eq!(game.hash(), hash);
+
+        // Only a piece a pawn can become will do
+        assert_eq!(game.promote(PieceKind::King), Err(MoveError::IllegalMove));
+        assert_eq!(game.pending_promotion(), Some(push));
+
+        assert_eq!(game.promote(PieceKind::Knight), Ok(()));
+        assert_eq!(game.pending_promotion(), None);
+        assert!(game.board().white_knights.is_set(60));
+        assert_eq!(game.promote(PieceKind::Queen), Err(MoveError::IllegalMove));
 
+        // Naming the piece up front plays the move straight away
+        game.undo();
+        assert_eq!(game.play(ChessMove::from_uci("e7e8r").unwrap()), Ok(MoveStatus::Played));
+        assert!(game.board().white_rooks.is_set(60));
+    }
+}
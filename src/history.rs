@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use crate::pieces::PieceColour;
 
 const MAX_GAME_MOVES: usize = 1024;
 
@@ -15,48 +15,70 @@ impl GameState {
             half_move_clock: 0,
         }
     }
+
+    // Create a state for a position with the given hash and half-move clock.
+    pub fn from_position(zobrist_hash: u64, half_move_clock: u16) -> Self {
+        Self {
+            zobrist_hash,
+            half_move_clock,
+        }
+    }
+
+    // Get the Zobrist hash of the position.
+    pub fn zobrist_hash(&self) -> u64 {
+        self.zobrist_hash
+    }
+
+    // Get the number of plies since the last pawn move or capture.
+    pub fn half_move_clock(&self) -> u16 {
+        self.half_move_clock
+    }
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct History {
     list: [GameState; MAX_GAME_MOVES],
     count: usize,
-    repetitions: HashMap<u64, usize>, // Tracks number of occurrences of a state
+    repetitions: [u16; MAX_GAME_MOVES], // Occurrences of each state's position up to and including it
+    starting_side: PieceColour, // Side to move in the position the game started from
 }
 
 impl History {
     // Create a new history array containing game states.
     pub fn new() -> Self {
+        Self::starting_from(PieceColour::White)
+    }
+
+    // Create an empty history for a game that starts with the given side to move.
+    pub fn starting_from(starting_side: PieceColour) -> Self {
         Self {
             list: [GameState::new(); MAX_GAME_MOVES],
             count: 0,
-            repetitions: HashMap::new(),
+            repetitions: [0; MAX_GAME_MOVES],
+            starting_side,
         }
     }
 
     // Put a new game state into the array.
     pub fn push(&mut self, g: GameState) {
+        // One more than the last time the position came up, if it has since the last pawn move
+        // or capture. Nothing before that can come round again.
+        let since = self.count.saturating_sub(g.half_move_clock as usize);
+        self.repetitions[self.count] = self.last_index_of(g.zobrist_hash, since).map_or(1, |i| self.repetitions[i] + 1);
         self.list[self.count] = g;
         self.count += 1;
-
-        // Update repetition count for the state
-        *self.repetitions.entry(g.zobrist_hash).or_insert(0) += 1;
     }
 
     // Return the last game state and decrement the counter.
     pub fn pop(&mut self) -> Option<GameState> {
         if self.count > 0 {
             self.count -= 1;
-            let state = self.list[self.count];
-
-            // Decrement repetition count
-            if let Some(entry) = self.repetitions.get_mut(&state.zobrist_hash) {
-                *entry -= 1;
-                if *entry == 0 {
-                    self.repetitions.remove(&state.zobrist_hash);
-                }
-            }
-
-            Some(state)
+            Some(self.list[self.count])
         } else {
             None
         }
@@ -67,26 +89,73 @@ impl History {
         &self.list[index]
     }
 
+    // Iterate over the states in the history, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &GameState> {
+        self.list[..self.count].iter()
+    }
+
     // Get the number of states in the history.
     pub fn len(&self) -> usize {
         self.count
     }
 
+    // Check if the history holds no states.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // Get the full move number of the current position, as used in PGN and FEN. Each
+    // state in the history is one ply, and a game starting with Black to move is
+    // already half way through move 1.
+    pub fn fullmove_number(&self) -> usize {
+        let plies = match self.starting_side {
+            PieceColour::White => self.count,
+            PieceColour::Black => self.count + 1,
+        };
+        1 + plies / 2
+    }
+
     // Clear the history.
     pub fn clear(&mut self) {
         self.count = 0;
-        self.repetitions.clear();
+    }
+
+    // Get how many times the position with the given hash occurs in the history, up to its
+    // latest occurrence and back to the last pawn move or capture before that.
+    pub fn repetition_count(&self, hash: u64) -> usize {
+        self.last_index_of(hash, 0).map_or(0, |i| self.repetitions[i] as usize)
+    }
+
+    // Find the most recent state with the given hash, looking no further back than `since`.
+    fn last_index_of(&self, hash: u64, since: usize) -> Option<usize> {
+        self.list[since..self.count].iter().rposition(|state| state.zobrist_hash == hash).map(|i| since + i)
     }
 
     // Check if a state has repeated three or more times.
     pub fn is_threefold_repetition(&self) -> bool {
-        self.repetitions.values().any(|&count| count >= 3)
+        self.repetitions[..self.count].iter().any(|&count| count >= 3)
     }
 
     // Check if the 50-move rule is applicable.
     pub fn is_fifty_move_rule(&self) -> bool {
         self.list[self.count - 1].half_move_clock >= 100
     }
+
+    // Check if a state has repeated five or more times, which draws without a claim.
+    pub fn is_fivefold_repetition(&self) -> bool {
+        self.repetitions[..self.count].iter().any(|&count| count >= 5)
+    }
+
+    // Check if the 75-move rule is applicable, which draws without a claim.
+    pub fn is_seventy_five_move_rule(&self) -> bool {
+        self.list[self.count - 1].half_move_clock >= 150
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 
@@ -122,18 +191,34 @@ mod tests {
         assert!(history.pop().is_none());
     }
 
+    #[test]
+    fn test_history_iter() {
+        let mut history = History::new();
+        for hash in [11, 22, 33] {
+            history.push(GameState::from_position(hash, 0));
+        }
+
+        let hashes: Vec<u64> = history.iter().map(GameState::zobrist_hash).collect();
+        assert_eq!(hashes, [11, 22, 33]);
+
+        // Popped states are no longer visited
+        history.pop();
+        assert_eq!(history.iter().count(), 2);
+        assert_eq!(History::new().iter().next(), None);
+    }
+
     #[test]
     fn test_threefold_repetition() {
         let mut history = History::new();
-        let game_state = GameState {
+        let game_state = |half_move_clock| GameState {
             zobrist_hash: 12345,
-            half_move_clock: 0,
+            half_move_clock,
         };
 
         // Push the same state three times
-        history.push(game_state);
-        history.push(game_state);
-        history.push(game_state);
+        history.push(game_state(0));
+        history.push(game_state(1));
+        history.push(game_state(2));
 
         assert!(history.is_threefold_repetition());
 
@@ -143,6 +228,87 @@ mod tests {
         assert!(!history.is_threefold_repetition());
     }
 
+    #[test]
+    fn test_repetition_count() {
+        let mut history = History::new();
+        let game_state = |half_move_clock| GameState {
+            zobrist_hash: 12345,
+            half_move_clock,
+        };
+
+        history.push(game_state(0));
+        history.push(game_state(1));
+
+        assert_eq!(history.repetition_count(12345), 2);
+        assert_eq!(history.repetition_count(67890), 0);
+    }
+
+    #[test]
+    fn test_repetitions_reset_by_irreversible_move() {
+        let mut history = History::new();
+        let a = |half_move_clock| GameState {
+            zobrist_hash: 12345,
+            half_move_clock,
+        };
+        let b = |half_move_clock| GameState {
+            zobrist_hash: 67890,
+            half_move_clock,
+        };
+
+        // A and B repeat, then a pawn move resets the clock before A shows up again, so the
+        // earlier occurrences of A don't count towards a repetition
+        history.push(a(0));
+        history.push(b(1));
+        history.push(a(2));
+        history.push(b(3));
+        history.push(a(0));
+
+        assert_eq!(history.repetition_count(12345), 1);
+        assert!(!history.is_threefold_repetition());
+
+        // Two more reversible shuffles make it a genuine repetition
+        history.push(b(1));
+        history.push(a(2));
+        history.push(b(3));
+        history.push(a(4));
+        assert!(history.is_threefold_repetition());
+    }
+
+    #[test]
+    fn test_fivefold_repetition() {
+        let mut history = History::new();
+        let game_state = |half_move_clock| GameState {
+            zobrist_hash: 12345,
+            half_move_clock,
+        };
+
+        for clock in 0..4 {
+            history.push(game_state(clock));
+        }
+        assert!(history.is_threefold_repetition());
+        assert!(!history.is_fivefold_repetition());
+
+        history.push(game_state(4));
+        assert!(history.is_fivefold_repetition());
+    }
+
+    #[test]
+    fn test_seventy_five_move_rule() {
+        let mut history = History::new();
+        history.push(GameState {
+            zobrist_hash: 12345,
+            half_move_clock: 149,
+        });
+        assert!(history.is_fifty_move_rule());
+        assert!(!history.is_seventy_five_move_rule());
+
+        history.push(GameState {
+            zobrist_hash: 67890,
+            half_move_clock: 150,
+        });
+        assert!(history.is_seventy_five_move_rule());
+    }
+
     #[test]
     fn test_fifty_move_rule() {
         let mut history = History::new();
@@ -181,6 +347,24 @@ mod tests {
         assert!(!history.is_threefold_repetition());
     }
 
+    #[test]
+    fn test_fullmove_number() {
+        let mut history = History::new();
+        assert_eq!(history.fullmove_number(), 1);
+
+        // 1. e4 e5 2. Nf3 leaves black to play move 2
+        for _ in 0..3 {
+            history.push(GameState::new());
+        }
+        assert_eq!(history.fullmove_number(), 2);
+
+        // Starting with black to move, black's first reply completes move 1
+        let mut history = History::starting_from(PieceColour::Black);
+        assert_eq!(history.fullmove_number(), 1);
+        history.push(GameState::new());
+        assert_eq!(history.fullmove_number(), 2);
+    }
+
     #[test]
     fn test_get_ref() {
         let mut history = History::new();
@@ -0,0 +1,49 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod board;
+pub mod pieces;
+pub mod game_logic;
+pub mod moves;
+pub mod zorbist;
+pub mod history;
+pub mod fen;
+pub mod rules;
+pub mod attacks;
+pub mod eval;
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(feature = "search-trace")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod uci;
+#[cfg(feature = "std")]
+pub mod pgn;
+#[cfg(feature = "std")]
+pub mod repl;
+
+// Core types without the std feature. `cargo build --no-default-features` checks they build as
+// no_std; tests always link std, so this only runs under `cargo test --no-default-features`.
+#[cfg(all(test, not(feature = "std")))]
+mod tests {
+    use crate::board::BoardState;
+    use crate::eval::evaluate;
+    use crate::history::{GameState, History};
+    use crate::moves::ChessMove;
+
+    #[test]
+    fn test_core_without_std() {
+        let mut board = BoardState::new();
+        assert_eq!(board.perft(3), 8902);
+        assert_eq!(evaluate(&board), 0);
+
+        let mut history = History::new();
+        history.push(GameState::from_position(board.hash(), 0));
+        for text in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            board.make_move(ChessMove::from_uci(text).unwrap());
+            history.push(GameState::from_position(board.hash(), board.half_move_clock));
+        }
+        assert_eq!(history.repetition_count(BoardState::new().hash()), 2);
+    }
+}
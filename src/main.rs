@@ -1,21 +1,22 @@
-use board::BoardState;
-use tracing::{info, span, Level};
-use tracing_subscriber;
+use std::io;
 
-mod board;
-mod pieces;
-mod game_logic;
-mod moves;
-mod zorbist;
-mod history;
+use jurgio_engine::repl;
+use jurgio_engine::uci::Uci;
+use tracing::Level;
 
-fn main() {
+fn main() -> io::Result<()> {
 
+    // Logs go to stderr, stdout belongs to the UCI protocol. Only warnings unless RUST_LOG
+    // names a level, such as RUST_LOG=debug, since move generation logs heavily at debug.
+    let level = std::env::var("RUST_LOG").ok().and_then(|level| level.parse().ok()).unwrap_or(Level::WARN);
     tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG) // Set log level
+        .with_max_level(level)
+        .with_writer(io::stderr)
         .init();
 
-    //let board = BoardState::new();
-
-    //board.print_board();
+    // `play` enters moves by hand; anything else speaks UCI
+    match std::env::args().nth(1).as_deref() {
+        Some("play") => repl::run(io::stdin().lock(), &mut io::stdout()),
+        _ => Uci::new().run(io::BufReader::new(io::stdin()), &mut io::stdout()),
+    }
 }
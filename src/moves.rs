@@ -1,7 +1,19 @@
-use crate::board::{BoardState, BitBoard};
-use crate::pieces::{PieceColour, PieceKind};
+use crate::board::{step, BoardState, BitBoard, Square, BISHOP_DIRECTIONS, KING_DELTAS, KNIGHT_DELTAS, ROOK_DIRECTIONS};
+use crate::pieces::{Piece, PieceColour, PieceKind};
+use crate::fen::{parse_square, square_name};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 use tracing;
 
+/// Ordering bonus that puts every capture ahead of the quiet moves.
+const CAPTURE_ORDER_BONUS: i32 = 10_000;
+/// The king's worth as a capturing piece for MVV-LVA, above any other piece so that taking
+/// with the king is tried last. `PieceKind::value` counts it as zero.
+const KING_ATTACKER_VALUE: i32 = 1_000;
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct ChessMove {
     pub from: usize, // Single index (0-63)
@@ -9,6 +21,126 @@ pub struct ChessMove {
     pub promotion: Option<PieceKind>,
 }
 
+/// Everything needed to take back a move made with `make_move`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MoveUndo {
+    pub chess_move: ChessMove,
+    pub captured: Option<Piece>,
+    pub castling_rights: [bool; 4],
+    pub en_passant_square: Option<usize>,
+    pub half_move_clock: u16,
+    pub zobrist: u64,
+    pub kind: MoveKind,
+}
+
+/// A move with the score it was given when ordering a move list, highest first.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ScoredMove {
+    pub chess_move: ChessMove,
+    pub score: i32,
+}
+
+/// Moves print in the long algebraic notation used by UCI, such as "e2e4" or "e7e8q".
+impl fmt::Display for ChessMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_null() {
+            return write!(f, "0000");
+        }
+
+        write!(f, "{}{}", square_name(self.from), square_name(self.to))?;
+        match self.promotion {
+            Some(PieceKind::Knight) => write!(f, "n"),
+            Some(PieceKind::Bishop) => write!(f, "b"),
+            Some(PieceKind::Rook) => write!(f, "r"),
+            Some(PieceKind::Queen) => write!(f, "q"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What a move does beyond carrying a piece from one square to another, worked out from
+/// the board it is played on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MoveKind {
+    Quiet,
+    Capture,
+    DoublePush,
+    EnPassant,
+    Castle,
+}
+
+impl MoveKind {
+    pub fn is_castle(&self) -> bool {
+        *self == MoveKind::Castle
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        *self == MoveKind::EnPassant
+    }
+}
+
+/// Errors that can occur while playing a move given by its squares.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    EmptySquare,
+    IllegalMove,
+    IllegalMoveAt(usize), // Index of the first move in a sequence that couldn't be played
+}
+
+impl ChessMove {
+    /// A "no move" sentinel, used for null-move pruning and empty move slots.
+    pub fn null() -> Self {
+        ChessMove {
+            from: 0,
+            to: 0,
+            promotion: None,
+        }
+    }
+
+    /// Check if this is the null move (no real move has `from == to`).
+    pub fn is_null(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Check if the move promotes a pawn. Castling and en passant depend on the board, see
+    /// `BoardState::move_kind`.
+    pub fn is_promotion(&self) -> bool {
+        self.promotion.is_some()
+    }
+
+    /// The same move reflected top to bottom, as the other side would play it: e2e4 becomes
+    /// e7e5. Files and the promotion piece stay the same.
+    pub fn mirror(&self) -> ChessMove {
+        ChessMove {
+            from: self.from ^ 56,
+            to: self.to ^ 56,
+            promotion: self.promotion,
+        }
+    }
+
+    /// Parse a move in UCI long algebraic notation, such as "e2e4" or "e7e8q".
+    pub fn from_uci(text: &str) -> Option<Self> {
+        if text.len() < 4 || !text.is_char_boundary(2) || !text.is_char_boundary(4) {
+            return None;
+        }
+
+        let promotion = match &text[4..] {
+            "" => None,
+            "n" => Some(PieceKind::Knight),
+            "b" => Some(PieceKind::Bishop),
+            "r" => Some(PieceKind::Rook),
+            "q" => Some(PieceKind::Queen),
+            _ => return None,
+        };
+
+        Some(ChessMove {
+            from: parse_square(&text[0..2])?,
+            to: parse_square(&text[2..4])?,
+            promotion,
+        })
+    }
+}
+
 impl BoardState {
     /// Generates all valid moves for the current player.
     pub fn generate_moves(&mut self) -> Vec<ChessMove> {
@@ -28,10 +160,249 @@ impl BoardState {
         moves
     }
 
+    /// Generates the moves that don't leave the mover's own king in check. Each move is tried on
+    /// the board, so this also catches an en passant capture that clears both pawns off the rank
+    /// and uncovers an attack along it.
+    pub fn generate_legal_moves(&mut self) -> Vec<ChessMove> {
+        let colour = self.to_move;
+        let mut moves = self.generate_moves();
+        moves.retain(|&m| {
+            let undo = self.make_move(m);
+            let legal = !self.is_in_check(colour);
+            self.unmake_move(undo);
+            legal
+        });
+        moves
+    }
+
+    /// Check if the side to move has any legal move, stopping at the first one found.
+    pub fn has_legal_move(&mut self) -> bool {
+        let colour = self.to_move;
+        self.generate_moves().into_iter().any(|m| {
+            let undo = self.make_move(m);
+            let legal = !self.is_in_check(colour);
+            self.unmake_move(undo);
+            legal
+        })
+    }
+
+    /// Count the leaf nodes of the legal move tree to the given depth, for checking the
+    /// move generator against known results.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let moves = self.generate_legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        let mut nodes = 0;
+        for m in moves {
+            let undo = self.make_move(m);
+            nodes += self.perft(depth - 1);
+            self.unmake_move(undo);
+        }
+        nodes
+    }
+
+    /// Count the same leaf nodes as `perft`, searching each root move on its own thread.
+    #[cfg(feature = "std")]
+    pub fn perft_parallel(&mut self, depth: u32) -> u64 {
+        if depth <= 1 {
+            return self.perft(depth);
+        }
+        self.perft_root_moves_parallel(depth).iter().map(|&(_, nodes, _)| nodes).sum()
+    }
+
+    /// The leaf nodes below each root move, each counted on its own thread, along with the
+    /// thread that counted them.
+    #[cfg(feature = "std")]
+    fn perft_root_moves_parallel(&mut self, depth: u32) -> Vec<(ChessMove, u64, std::thread::ThreadId)> {
+        let moves = self.generate_legal_moves();
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = moves
+                .into_iter()
+                .map(|m| {
+                    let mut board = self.clone();
+                    scope.spawn(move || {
+                        board.make_move(m);
+                        (m, board.perft(depth - 1), std::thread::current().id())
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().expect("perft worker panicked")).collect()
+        })
+    }
+
+    /// Generates the legal moves keyed by the square the moving piece starts on.
+    #[cfg(feature = "std")]
+    pub fn legal_moves_grouped(&mut self) -> HashMap<usize, Vec<ChessMove>> {
+        let mut grouped: HashMap<usize, Vec<ChessMove>> = HashMap::new();
+        for m in self.generate_legal_moves() {
+            grouped.entry(m.from).or_default().push(m);
+        }
+        grouped
+    }
+
+    /// Generates the legal moves that land on the given square, such as every way to recapture there.
+    pub fn moves_targeting(&mut self, square: usize) -> Vec<ChessMove> {
+        let colour = self.to_move;
+        let mut moves = self.generate_moves();
+        moves.retain(|&m| {
+            if m.to != square {
+                return false;
+            }
+            let undo = self.make_move(m);
+            let legal = !self.is_in_check(colour);
+            self.unmake_move(undo);
+            legal
+        });
+        moves
+    }
+
+    /// Generates the moves that capture a piece, including en passant.
+    pub fn generate_captures(&mut self) -> Vec<ChessMove> {
+        let mut moves = self.generate_moves();
+        moves.retain(|m| self.is_capture(m));
+        moves
+    }
+
+    /// Check that nothing sharp is going on: the side to move isn't in check and has no legal
+    /// capture or promotion, so the static eval can be trusted as it stands.
+    pub fn is_quiet(&mut self) -> bool {
+        !self.side_to_move_in_check()
+            && !self
+                .generate_legal_moves()
+                .iter()
+                .any(|m| m.is_promotion() || self.is_capture(m))
+    }
+
+    /// Check whether the side not to move could only move its king if it were its turn, or
+    /// not move at all. Such a side is close to stalemate, so the side to move needs to take
+    /// care not to take the king's last squares away. Always false when the side to move is in
+    /// check, as passing the turn to look at the opponent's moves would leave its king en prise.
+    pub fn opponent_has_only_king_moves(&mut self) -> bool {
+        if self.side_to_move_in_check() {
+            return false;
+        }
+        let undo = self.make_move(ChessMove::null());
+        let king = self.king_square(self.to_move);
+        let only_king_moves = self.generate_legal_moves().iter().all(|m| m.from == king);
+        self.unmake_move(undo);
+        only_king_moves
+    }
+
+    /// Generates the legal moves that give check, directly or by uncovering a line.
+    pub fn generate_checks(&mut self) -> Vec<ChessMove> {
+        let mut moves = self.generate_legal_moves();
+        moves.retain(|&m| self.move_gives_check(m));
+        moves
+    }
+
+    /// Check whether playing the move leaves the opponent in check.
+    pub fn move_gives_check(&mut self, chess_move: ChessMove) -> bool {
+        let undo = self.make_move(chess_move);
+        let check = self.side_to_move_in_check();
+        self.unmake_move(undo);
+        check
+    }
+
+    /// Check whether playing the move checkmates the opponent.
+    pub fn move_is_mate(&mut self, chess_move: ChessMove) -> bool {
+        let undo = self.make_move(chess_move);
+        let mate = self.side_to_move_in_check() && !self.has_legal_move();
+        self.unmake_move(undo);
+        mate
+    }
+
+    /// Generates the moves that don't capture anything.
+    pub fn generate_quiet_moves(&mut self) -> Vec<ChessMove> {
+        let mut moves = self.generate_moves();
+        moves.retain(|m| !self.is_capture(m));
+        moves
+    }
+
+    /// Sort moves best first for the search: captures by most valuable victim, then least
+    /// valuable attacker (MVV-LVA), promotions by the piece promoted to, then quiet moves.
+    pub fn order_moves(&self, moves: Vec<ChessMove>) -> Vec<ScoredMove> {
+        let mut scored: Vec<ScoredMove> = moves
+            .into_iter()
+            .map(|chess_move| ScoredMove {
+                chess_move,
+                score: self.order_score(&chess_move),
+            })
+            .collect();
+        scored.sort_by_key(|scored| Reverse(scored.score));
+        scored
+    }
+
+    fn order_score(&self, chess_move: &ChessMove) -> i32 {
+        let mut score = 0;
+        if let Some((victim, attacker)) = self.capture_pieces(chess_move) {
+            score += CAPTURE_ORDER_BONUS + 10 * victim.value() - attacker_value(attacker);
+        }
+        if let Some(promotion) = chess_move.promotion {
+            score += promotion.value();
+        }
+        score
+    }
+
+    /// Sort key putting captures in MVV-LVA order, smallest first: most valuable victim, then
+    /// least valuable attacker, then the from and to squares so that equal captures still come
+    /// out in a fixed order. Non-captures count as taking nothing and sort last.
+    pub fn capture_order_key(&self, chess_move: &ChessMove) -> (Reverse<i32>, i32, usize, usize) {
+        let (victim, attacker) = self
+            .capture_pieces(chess_move)
+            .map_or((0, 0), |(victim, attacker)| (victim.value(), attacker_value(attacker)));
+        (Reverse(victim), attacker, chess_move.from, chess_move.to)
+    }
+
+    /// The kinds of the captured and capturing pieces, if the move is a capture.
+    fn capture_pieces(&self, chess_move: &ChessMove) -> Option<(PieceKind, PieceKind)> {
+        if !self.is_capture(chess_move) {
+            return None;
+        }
+        // Anything missing from the target square was taken en passant
+        let victim = self.piece_at(chess_move.to).map_or(PieceKind::Pawn, |piece| piece.kind);
+        let attacker = self.piece_at(chess_move.from).map_or(PieceKind::Pawn, |piece| piece.kind);
+        Some((victim, attacker))
+    }
+
+    /// Check if a move captures a piece, either on its target square or en passant.
+    pub fn is_capture(&self, chess_move: &ChessMove) -> bool {
+        if self.is_opponent_piece(chess_move.to, self.to_move) {
+            return true;
+        }
+
+        self.en_passant_square == Some(chess_move.to)
+            && self.piece_at(chess_move.from).is_some_and(|piece| piece.kind == PieceKind::Pawn)
+            && chess_move.from % 8 != chess_move.to % 8
+    }
+
+    /// Classify a move by inspecting the board it is about to be played on. `make_move` keeps
+    /// the result in the `MoveUndo`, so the move can be taken back without looking again.
+    pub fn move_kind(&self, chess_move: &ChessMove) -> MoveKind {
+        let Some(piece) = self.piece_at(chess_move.from).filter(|_| !chess_move.is_null()) else {
+            return MoveKind::Quiet;
+        };
+        let file_distance = (chess_move.from % 8).abs_diff(chess_move.to % 8);
+        let rank_distance = (chess_move.from / 8).abs_diff(chess_move.to / 8);
+
+        match piece.kind {
+            PieceKind::King if file_distance == 2 => MoveKind::Castle,
+            PieceKind::Pawn if rank_distance == 2 => MoveKind::DoublePush,
+            PieceKind::Pawn if file_distance == 1 && self.is_empty(chess_move.to) => MoveKind::EnPassant,
+            _ if self.all_pieces.is_set(chess_move.to) => MoveKind::Capture,
+            _ => MoveKind::Quiet,
+        }
+    }
+
     /// Generate moves for a specific color.
     fn generate_colour_moves(&mut self, pieces: &BitBoard, moves: &mut Vec<ChessMove>) {
         tracing::debug!("All white bitboard: {:064b}", self.all_white.0);
-        for square in pieces.iter() {
+        for square in pieces.iter().map(Square::index) {
             tracing::debug!("Iterating square: {}", square);
             if let Some(piece) = self.piece_at(square) {
                 tracing::debug!("Processing piece: {:?} at square {}", piece, square);
@@ -51,21 +422,17 @@ impl BoardState {
 
     /// Generate pawn moves, including promotions and en passant.
     fn generate_pawn_moves(&mut self, square: usize, colour: PieceColour, moves: &mut Vec<ChessMove>) {
-        let direction = if colour == PieceColour::White { 8 } else { -8 };
+        let direction = colour.pawn_push_offset();
         let forward = square as isize + direction;
 
         // Single forward move
-        if forward >= 0 && forward < 64 && !self.all_pieces.is_set(forward as usize) {
-            moves.push(ChessMove {
-                from: square,
-                to: forward as usize,
-                promotion: self.promotion_check(forward as usize, colour),
-            });
+        if (0..64).contains(&forward) && self.is_empty(forward as usize) {
+            push_pawn_move(square, forward as usize, colour, moves);
 
             // Double forward move from starting rank
             if self.is_pawn_starting_rank(square, colour) {
                 let double_forward = square as isize + 2 * direction;
-                if double_forward >= 0 && double_forward < 64 && !self.all_pieces.is_set(double_forward as usize) {
+                if (0..64).contains(&double_forward) && self.is_empty(double_forward as usize) {
                     tracing::debug!(
                         "Checking two-square move for pawn at {}: direction={} double_forward={}",
                         square,
@@ -78,53 +445,29 @@ impl BoardState {
                         to: double_forward as usize,
                         promotion: None,
                     });
-            
-                    // Set en passant square for the opponent only on a valid two-square move
-                    self.en_passant_square = Some((square as isize + direction) as usize);
-                    tracing::debug!("Set en_passant_square={:?}", self.en_passant_square);
                 }
             }
         }
 
         // Captures
-        let capture_offsets = if colour == PieceColour::White { [-9, -7] } else { [7, 9] };
-        for &offset in &capture_offsets {
-            let target = square as isize + offset;
+        let forward_rank = (direction / 8) as i8;
+        for file_delta in [-1, 1] {
+            let Some(target) = step(square, forward_rank, file_delta) else {
+                continue;
+            };
 
             // Standard capture
-            if target >= 0
-                && target < 64
-                && self.all_pieces.is_set(target as usize)
-                && self.is_opponent_piece(target as usize, colour)
-            {
+            if self.is_opponent_piece(target, colour) {
+                push_pawn_move(square, target, colour, moves);
+            } else if self.en_passant_square == Some(target) {
+                // En passant capture
                 moves.push(ChessMove {
                     from: square,
-                    to: target as usize,
-                    promotion: self.promotion_check(target as usize, colour),
+                    to: target,
+                    promotion: None,
                 });
+                tracing::debug!("Generated en passant move from {} to {}", square, target);
             }
-
-            // En passant capture
-            if let Some(ep_square) = self.en_passant_square {
-                if (square == ep_square - 9 || square == ep_square - 7 || // White pawn capture
-                    square == ep_square + 9 || square == ep_square + 7) { // Black pawn capture
-                    moves.push(ChessMove {
-                        from: square,
-                        to: ep_square,
-                        promotion: None,
-                    });
-                    tracing::debug!("Generated en passant move from {} to {}", square, ep_square);
-                } else {
-                    tracing::debug!(
-                        "Skipped en passant for square {}: no legal pawn to capture ep_square={}",
-                        square,
-                        ep_square
-                    );
-                }
-            }
-
-            
-            
         }
     }
 
@@ -138,54 +481,19 @@ impl BoardState {
 
     /// Determine if a pawn is on its starting rank.
     fn is_pawn_starting_rank(&self, square: usize, colour: PieceColour) -> bool {
-        match colour {
-            PieceColour::White => (8..16).contains(&square),
-            PieceColour::Black => (48..56).contains(&square),
-        }
+        colour.starting_rank_range().contains(&square)
     }
 
-    /// Check if a pawn move results in promotion.
-    fn promotion_check(&self, square: usize, colour: PieceColour) -> Option<PieceKind> {
-        match colour {
-            PieceColour::White if square < 8 => Some(PieceKind::Queen),
-            PieceColour::Black if square >= 56 => Some(PieceKind::Queen),
-            _ => None,
-        }
-    } 
-    
     /// Generate knight moves.
     fn generate_knight_moves(&self, square: usize, moves: &mut Vec<ChessMove>) {
-    
-        let offsets = [17, 15, 10, 6, -17, -15, -10, -6];
-        let rank = (square / 8) as isize; // Current rank (0 to 7)
-        let file = (square % 8) as isize; // Current file (0 to 7)
-    
-        for &offset in &offsets {
-            let target = square as isize + offset;
-    
-            // Check if target is on the board
-            if target >= 0 && target < 64 {
-                let target_rank = target / 8;
-                let target_file = target % 8;
-    
-                // Validate file difference for wrapping prevention
-                let file_diff = (target_file - file).abs();
-                tracing::debug!(
-                    target,
-                    target_rank,
-                    target_file,
-                    file_diff,
-                    "Calculating knight move"
-                );
-    
-                // Ensure the move stays within valid ranks and files
-                if (offset.abs() == 17 || offset.abs() == 15) && file_diff == 1
-                    || (offset.abs() == 10 || offset.abs() == 6) && file_diff == 2
-                {
+        for &(rank_delta, file_delta) in &KNIGHT_DELTAS {
+            // Skip jumps off the board or onto our own pieces
+            if let Some(target) = step(square, rank_delta, file_delta) {
+                if self.is_empty(target) || self.is_opponent_piece(target, self.to_move) {
                     tracing::debug!(from = square, to = target, "Adding knight move");
                     moves.push(ChessMove {
                         from: square,
-                        to: target as usize,
+                        to: target,
                         promotion: None,
                     });
                 }
@@ -195,24 +503,26 @@ impl BoardState {
 
     /// Generate bishop moves.
     fn generate_bishop_moves(&self, square: usize, moves: &mut Vec<ChessMove>) {
-        self.generate_sliding_moves(square, &[9, 7, -9, -7], moves);
+        self.generate_sliding_moves(square, &BISHOP_DIRECTIONS, moves);
     }
 
     /// Generate rook moves.
     fn generate_rook_moves(&self, square: usize, moves: &mut Vec<ChessMove>) {
-        self.generate_sliding_moves(square, &[8, -8, 1, -1], moves);
+        self.generate_sliding_moves(square, &ROOK_DIRECTIONS, moves);
     }
 
     /// Generate queen moves (combining rook and bishop).
     fn generate_queen_moves(&self, square: usize, moves: &mut Vec<ChessMove>) {
-        self.generate_sliding_moves(square, &[9, 7, -9, -7, 8, -8, 1, -1], moves);
+        self.generate_sliding_moves(square, &KING_DELTAS, moves);
     }
 
     /// Generate king moves.
     fn generate_king_moves(&self, square: usize, moves: &mut Vec<ChessMove>) {
-        for &offset in &[9, 7, -9, -7, 8, -8, 1, -1] {
-            let target = (square as isize + offset) as usize;
-            if target < 64 && (!self.all_pieces.is_set(target) || self.is_opponent_piece(target, self.to_move)) {
+        for &(rank_delta, file_delta) in &KING_DELTAS {
+            let Some(target) = step(square, rank_delta, file_delta) else {
+                continue;
+            };
+            if self.is_empty(target) || self.is_opponent_piece(target, self.to_move) {
                 moves.push(ChessMove {
                     from: square,
                     to: target,
@@ -221,22 +531,17 @@ impl BoardState {
             }
         }
     
-        // Add castling logic
+        // Castling; the can_castle checks cover the squares the king passes through
         if self.can_castle_kingside(self.to_move) {
             let (king_from, king_to) = match self.to_move {
                 PieceColour::White => (4, 6),
                 PieceColour::Black => (60, 62),
             };
-            if self.is_square_safe(king_from)
-                && self.is_square_safe(king_from + 1)
-                && self.is_square_safe(king_from + 2)
-            {
-                moves.push(ChessMove {
-                    from: king_from,
-                    to: king_to,
-                    promotion: None,
-                });
-            }
+            moves.push(ChessMove {
+                from: king_from,
+                to: king_to,
+                promotion: None,
+            });
         }
     
         if self.can_castle_queenside(self.to_move) {
@@ -244,32 +549,26 @@ impl BoardState {
                 PieceColour::White => (4, 2),
                 PieceColour::Black => (60, 58),
             };
-            if self.is_square_safe(king_from)
-                && self.is_square_safe(king_from - 1)
-                && self.is_square_safe(king_from - 2)
-            {
-                moves.push(ChessMove {
-                    from: king_from,
-                    to: king_to,
-                    promotion: None,
-                });
-            }
+            moves.push(ChessMove {
+                from: king_from,
+                to: king_to,
+                promotion: None,
+            });
         }
     }
     
     
 
     /// Helper for sliding piece moves (bishop, rook, queen).
-    fn generate_sliding_moves(&self, square: usize, directions: &[isize], moves: &mut Vec<ChessMove>) {
-        for &direction in directions {
-            let mut target = square as isize + direction;
-            while target >= 0 && target < 64 {
-                let target_usize = target as usize;
-                if self.all_pieces.is_set(target_usize) {
-                    if self.is_opponent_piece(target_usize, self.to_move) {
+    fn generate_sliding_moves(&self, square: usize, directions: &[(i8, i8)], moves: &mut Vec<ChessMove>) {
+        for &(rank_delta, file_delta) in directions {
+            let mut target = step(square, rank_delta, file_delta);
+            while let Some(target_square) = target {
+                if self.all_pieces.is_set(target_square) {
+                    if self.is_opponent_piece(target_square, self.to_move) {
                         moves.push(ChessMove {
                             from: square,
-                            to: target_usize,
+                            to: target_square,
                             promotion: None,
                         });
                     }
@@ -277,10 +576,10 @@ impl BoardState {
                 }
                 moves.push(ChessMove {
                     from: square,
-                    to: target_usize,
+                    to: target_square,
                     promotion: None,
                 });
-                target += direction;
+                target = step(target_square, rank_delta, file_delta);
             }
         }
     }
@@ -289,11 +588,31 @@ impl BoardState {
 
 
 
+/// Value of a capturing piece in the LVA half of MVV-LVA.
+fn attacker_value(attacker: PieceKind) -> i32 {
+    match attacker {
+        PieceKind::King => KING_ATTACKER_VALUE,
+        kind => kind.value(),
+    }
+}
+
+/// Pieces a pawn can promote to, best first.
+const PROMOTION_KINDS: [PieceKind; 4] = [PieceKind::Queen, PieceKind::Rook, PieceKind::Bishop, PieceKind::Knight];
+
+/// Add a pawn move, or one move per promotion piece when it reaches the colour's last rank.
+fn push_pawn_move(from: usize, to: usize, colour: PieceColour, moves: &mut Vec<ChessMove>) {
+    if to / 8 != colour.promotion_rank() {
+        moves.push(ChessMove { from, to, promotion: None });
+    } else {
+        moves.extend(PROMOTION_KINDS.iter().map(|&kind| ChessMove { from, to, promotion: Some(kind) }));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::board::BoardState;
-    use crate::pieces::{PieceColour, PieceKind};
+    use crate::pieces::PieceColour;
     use tracing_subscriber;
 
     fn init() {
@@ -329,9 +648,7 @@ mod tests {
         let mut board = BoardState::new();
 
         // Place a white knight at d4 (square 27)
-        board.white_knights.set(27);
-        board.all_white.set(27);
-        board.all_pieces.set(27);
+        board.add_piece(27, Piece { kind: PieceKind::Knight, colour: PieceColour::White });
 
         tracing::info!("Set up board for knight at d4");
 
@@ -352,10 +669,12 @@ mod tests {
             ChessMove { from: 27, to: 33, promotion: None }, // c3
             ChessMove { from: 27, to: 17, promotion: None }, // b6
             ChessMove { from: 27, to: 21, promotion: None }, // b3
-            ChessMove { from: 27, to: 12, promotion: None }, // e2
-            ChessMove { from: 27, to: 10, promotion: None }, // f2
         ];
 
+        // c2 and e2 hold white pawns, so the knight can't move there
+        assert!(!moves.contains(&ChessMove { from: 27, to: 10, promotion: None }));
+        assert!(!moves.contains(&ChessMove { from: 27, to: 12, promotion: None }));
+
         // Check if all expected moves are in the generated moves
         for m in expected_moves {
             assert!(
@@ -390,6 +709,129 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_null_move() {
+        assert!(ChessMove::null().is_null());
+        assert!(!ChessMove { from: 12, to: 28, promotion: None }.is_null());
+    }
+
+    #[test]
+    fn test_is_promotion() {
+        assert!(ChessMove::from_uci("e7e8q").unwrap().is_promotion());
+        assert!(!ChessMove::from_uci("e2e4").unwrap().is_promotion());
+    }
+
+    #[test]
+    fn test_move_is_mate() {
+        // Fool's mate: 1.f3 e5 2.g4 Qh4#
+        let mut board = BoardState::new();
+        for text in ["f2f3", "e7e5", "g2g4"] {
+            board.make_move(ChessMove::from_uci(text).unwrap());
+        }
+        let before = board.clone();
+
+        assert!(board.move_is_mate(ChessMove::from_uci("d8h4").unwrap()));
+        assert!(!board.move_is_mate(ChessMove::from_uci("d8g5").unwrap()));
+        assert_eq!(board, before);
+
+        // A check the king can step out of is not mate
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert!(!board.move_is_mate(ChessMove::from_uci("a1a8").unwrap()));
+    }
+
+    #[test]
+    fn test_opponent_has_only_king_moves() {
+        assert!(!BoardState::new().opponent_has_only_king_moves());
+
+        // The h7 pawn is blocked and the white king covers g7, leaving black just Kg8
+        let mut board = BoardState::from_fen("7k/7p/5K1P/8/8/8/8/8 w - - 0 1").unwrap();
+        let before = board.clone();
+        assert!(board.opponent_has_only_king_moves());
+        assert_eq!(board, before);
+
+        // Without the h6 pawn the h-pawn is free to move
+        let mut free_pawn = BoardState::from_fen("7k/7p/5K2/8/8/8/7P/8 w - - 0 1").unwrap();
+        assert!(!free_pawn.opponent_has_only_king_moves());
+
+        // With the white king in check the question isn't asked
+        let mut in_check = BoardState::from_fen("7k/7p/5K1P/8/8/8/8/5r2 w - - 0 1").unwrap();
+        assert!(!in_check.opponent_has_only_king_moves());
+    }
+
+    #[test]
+    fn test_is_quiet() {
+        assert!(BoardState::new().is_quiet());
+
+        // The e5 pawn can take the knight on d6
+        assert!(!BoardState::from_fen("4k3/8/3n4/4P3/8/8/8/4K3 w - - 0 1").unwrap().is_quiet());
+        // A pawn about to promote
+        assert!(!BoardState::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap().is_quiet());
+        // In check from the rook
+        assert!(!BoardState::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").unwrap().is_quiet());
+        // The only capture would leave the king in check from the e8 rook
+        assert!(BoardState::from_fen("4r1k1/8/8/2n5/8/4B3/8/4K3 w - - 0 1").unwrap().is_quiet());
+    }
+
+    #[test]
+    fn test_mirror() {
+        assert_eq!(ChessMove::from_uci("e2e4").unwrap().mirror(), ChessMove::from_uci("e7e5").unwrap());
+        assert_eq!(ChessMove::from_uci("b7a8n").unwrap().mirror(), ChessMove::from_uci("b2a1n").unwrap());
+
+        let castle = ChessMove::from_uci("e1g1").unwrap();
+        assert_eq!(castle.mirror().mirror(), castle);
+    }
+
+    #[test]
+    fn test_uci_notation() {
+        let promotion = ChessMove { from: 52, to: 60, promotion: Some(PieceKind::Queen) };
+        assert_eq!(promotion.to_string(), "e7e8q");
+        assert_eq!(ChessMove::from_uci("e7e8q"), Some(promotion));
+        assert_eq!(ChessMove::from_uci("e2e4"), Some(ChessMove { from: 12, to: 28, promotion: None }));
+        assert_eq!(ChessMove::null().to_string(), "0000");
+
+        assert_eq!(ChessMove::from_uci("e2e9"), None);
+        assert_eq!(ChessMove::from_uci("e7e8k"), None);
+    }
+
+    #[test]
+    fn test_quiet_moves_never_capture() {
+        init();
+        let mut board = BoardState::from_fen(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQK2R w KQkq - 4 5",
+        )
+        .unwrap();
+
+        let quiet = board.generate_quiet_moves();
+        assert!(!quiet.is_empty());
+        for m in &quiet {
+            assert!(!board.all_black.is_set(m.to), "Quiet move {:?} lands on a black piece", m);
+        }
+
+        // Nf3xe5 is a capture and must be reported as one instead
+        let captures = board.generate_captures();
+        assert!(captures.contains(&ChessMove { from: 21, to: 36, promotion: None }));
+        assert!(!quiet.contains(&ChessMove { from: 21, to: 36, promotion: None }));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_legal_moves_grouped_initial_board() {
+        init();
+        let mut board = BoardState::new();
+
+        let grouped = board.legal_moves_grouped();
+
+        // Eight pawns and two knights can move, two squares each
+        assert_eq!(grouped.len(), 10);
+        assert_eq!(grouped.values().map(Vec::len).sum::<usize>(), 20);
+        for square in 8..16 {
+            assert_eq!(grouped[&square].len(), 2, "pawn on {}", square);
+        }
+        assert_eq!(grouped[&1].len(), 2); // b1 knight
+        assert_eq!(grouped[&6].len(), 2); // g1 knight
+        assert!(grouped[&6].iter().all(|m| m.from == 6));
+    }
+
     #[test]
     fn test_initial_setup() {
         init();
@@ -478,5 +920,285 @@ mod tests {
         }), "En passant capture is missing");
     }
 
-    
+    #[test]
+    fn test_en_passant_pinned_along_rank() {
+        // bxc6 would take both pawns off the fifth rank and expose the king to the h5 rook
+        let b5c6 = ChessMove { from: 33, to: 42, promotion: None };
+        let mut pinned = BoardState::from_fen("8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1").unwrap();
+        assert!(pinned.generate_moves().contains(&b5c6));
+        assert!(!pinned.generate_legal_moves().contains(&b5c6));
+
+        let mut free = BoardState::from_fen("8/8/8/KPp5/8/8/8/4k2r w - c6 0 1").unwrap();
+        assert!(free.generate_legal_moves().contains(&b5c6));
+    }
+
+
+    #[test]
+    fn test_black_to_move_from_fen() {
+        init();
+        // After 1.e4 the FEN names e3, but no black pawn can take on it
+        let mut board =
+            BoardState::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert_eq!(board.en_passant_square, None);
+
+        let moves = board.generate_legal_moves();
+        assert_eq!(moves.len(), 20);
+        assert!(moves.iter().all(|m| board.all_black.is_set(m.from)));
+
+        // ...e5 leaves no en passant square, as no white pawn could take on e6
+        let e5 = ChessMove { from: 52, to: 36, promotion: None };
+        assert!(moves.contains(&e5));
+        board.make_move(e5);
+        assert_eq!(board.to_move, PieceColour::White);
+        assert_eq!(board.en_passant_square, None);
+    }
+
+    #[test]
+    fn test_black_special_moves_from_fen() {
+        init();
+        // Black can castle both ways and take the f4 pawn en passant on f3
+        let mut board = BoardState::from_fen("r3k2r/8/8/8/4pP2/8/8/R3K2R b KQkq f3 0 1").unwrap();
+        let moves = board.generate_legal_moves();
+
+        assert!(moves.contains(&ChessMove { from: 60, to: 62, promotion: None }));
+        assert!(moves.contains(&ChessMove { from: 60, to: 58, promotion: None }));
+
+        let en_passant = ChessMove { from: 28, to: 21, promotion: None };
+        assert!(moves.contains(&en_passant));
+        board.make_move(en_passant);
+        assert!(!board.white_pawns.is_set(29)); // f4 pawn is gone
+        assert!(board.black_pawns.is_set(21));
+    }
+
+    #[test]
+    fn test_move_kind() {
+        let board = BoardState::from_fen("r3k2r/8/8/3Pp3/8/8/4P3/R3K2R w KQkq e6 0 1").unwrap();
+
+        assert!(board.move_kind(&ChessMove { from: 4, to: 6, promotion: None }).is_castle());
+        assert!(board.move_kind(&ChessMove { from: 35, to: 44, promotion: None }).is_en_passant());
+        assert_eq!(board.move_kind(&ChessMove { from: 12, to: 28, promotion: None }), MoveKind::DoublePush);
+        assert_eq!(board.move_kind(&ChessMove { from: 0, to: 56, promotion: None }), MoveKind::Capture);
+        assert_eq!(board.move_kind(&ChessMove { from: 4, to: 5, promotion: None }), MoveKind::Quiet);
+
+        // make_move plays the move by its kind and unmake_move takes it back the same way
+        let mut played = board.clone();
+        let undo = played.make_move(ChessMove { from: 35, to: 44, promotion: None });
+        assert_eq!(undo.kind, MoveKind::EnPassant);
+        assert!(played.is_empty(36));
+        played.unmake_move(undo);
+        assert_eq!(played, board);
+    }
+
+    #[test]
+    fn test_perft_initial_position() {
+        let mut board = BoardState::new();
+        for (depth, nodes) in [(1, 20), (2, 400), (3, 8902)] {
+            assert_eq!(board.perft(depth), nodes, "perft({})", depth);
+        }
+        assert_eq!(board, BoardState::new());
+    }
+
+    #[test]
+    fn test_perft_promotions() {
+        // Pawns on the seventh rank for both sides, with captures onto the back rank
+        let mut board = BoardState::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+        assert_eq!(board.perft(1), 24);
+        assert_eq!(board.perft(2), 496);
+        assert_eq!(board.perft(3), 9483);
+
+        // g2-g1 promotes to each of the four pieces
+        let promotions: Vec<ChessMove> =
+            board.generate_legal_moves().into_iter().filter(|m| m.from == 14 && m.to == 6).collect();
+        assert_eq!(promotions.len(), 4, "{:?}", promotions);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_perft_parallel() {
+        let mut board = BoardState::new();
+        assert_eq!(board.perft_parallel(3), board.perft(3));
+        assert_eq!(board.perft_parallel(4), 197281);
+
+        // Every root move is counted on a thread of its own, none of them this one
+        let root_moves = board.perft_root_moves_parallel(3);
+        assert_eq!(root_moves.len(), 20);
+        let threads: std::collections::HashSet<_> = root_moves.iter().map(|&(_, _, thread)| thread).collect();
+        assert_eq!(threads.len(), 20);
+        assert!(!threads.contains(&std::thread::current().id()));
+
+        for (m, nodes, _) in root_moves {
+            let undo = board.make_move(m);
+            assert_eq!(nodes, board.perft(2), "{}", m);
+            board.unmake_move(undo);
+        }
+    }
+
+    // The full count from the request, which takes a while in a debug build:
+    // `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    #[cfg(feature = "std")]
+    fn test_perft_parallel_depth_5() {
+        assert_eq!(BoardState::new().perft_parallel(5), 4865609);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        // Castling, en passant and promotions all show up in the first few plies here
+        let mut board =
+            BoardState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for (depth, nodes) in [(1, 48), (2, 2039), (3, 97862)] {
+            assert_eq!(board.perft(depth), nodes, "perft({})", depth);
+        }
+    }
+
+    #[test]
+    fn test_generate_checks() {
+        // Only g7 checks the king on h8
+        let mut board = BoardState::from_fen("7k/8/6P1/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(board.generate_checks(), vec![ChessMove::from_uci("g6g7").unwrap()]);
+
+        // Every knight move uncovers the rook on the h-file
+        let mut board = BoardState::from_fen("7k/8/8/8/7N/8/8/K6R w - - 0 1").unwrap();
+        let checks = board.generate_checks();
+        assert_eq!(checks.len(), 4);
+        assert!(checks.iter().all(|m| m.from == 31));
+    }
+
+    #[test]
+    fn test_moves_targeting() {
+        // The f4 pawn and f3 knight both defend e5; the d3 knight is pinned to its king
+        let mut board = BoardState::from_fen("3r2k1/8/8/4n3/5P2/3N1N2/8/3K4 w - - 0 1").unwrap();
+        let mut moves: Vec<String> = board.moves_targeting(36).iter().map(|m| m.to_string()).collect();
+        moves.sort();
+        assert_eq!(moves, ["f3e5", "f4e5"]);
+
+        assert!(board.moves_targeting(63).is_empty());
+    }
+
+    /// The legal moves from a position in UCI notation, sorted.
+    fn sorted_legal_moves(fen: &str) -> Vec<String> {
+        let mut board = BoardState::from_fen(fen).unwrap();
+        let mut moves: Vec<String> = board.generate_legal_moves().iter().map(|m| m.to_string()).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn test_legal_moves_match_reference() {
+        // The lists come from a separate move generator outside this crate, a plain 8x8 array
+        // one that tries every move and looks for attacks on the king, which reproduces the
+        // published perft counts for the start position and the standard test positions. The
+        // last three are such positions, whose move counts are published too.
+        let cases: [(&str, &[&str]); 7] = [
+            // The e2 rook is pinned to its king and can only slide along the e-file
+            (
+                "4r1k1/8/8/8/8/8/4R3/4K3 w - - 0 1",
+                &["e1d1", "e1d2", "e1f1", "e1f2", "e2e3", "e2e4", "e2e5", "e2e6", "e2e7", "e2e8"],
+            ),
+            // bxc6 en passant would open the fifth rank to the h5 rook, and c5 covers b4
+            ("8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1", &["a5a4", "a5a6", "a5b6", "b5b6"]),
+            // Double check from the knight and rook leaves only king moves
+            ("4k3/8/8/8/8/5n2/8/4K2r w - - 0 1", &["e1e2", "e1f2"]),
+            // The f8 rook covers f1, so only queenside castling is allowed
+            (
+                "4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+                &[
+                    "a1a2", "a1a3", "a1a4", "a1a5", "a1a6", "a1a7", "a1a8", "a1b1", "a1c1", "a1d1", "e1c1",
+                    "e1d1", "e1d2", "e1e2", "h1f1", "h1g1", "h1h2", "h1h3", "h1h4", "h1h5", "h1h6", "h1h7",
+                    "h1h8",
+                ],
+            ),
+            // Position 3: the b5 pawn shields the a5 king from the h5 rook
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                &[
+                    "a5a4", "a5a6", "b4a4", "b4b1", "b4b2", "b4b3", "b4c4", "b4d4", "b4e4", "b4f4", "e2e3",
+                    "e2e4", "g2g3", "g2g4",
+                ],
+            ),
+            // Position 4: the b6 bishop gives check down the long diagonal, with six ways out
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                &["b4c5", "c4c5", "d2d4", "f1f2", "f3d4", "g1h1"],
+            ),
+            // Kiwipete: castling both ways, pins and captures everywhere
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[
+                    "a1b1", "a1c1", "a1d1", "a2a3", "a2a4", "b2b3", "c3a4", "c3b1", "c3b5", "c3d1", "d2c1",
+                    "d2e3", "d2f4", "d2g5", "d2h6", "d5d6", "d5e6", "e1c1", "e1d1", "e1f1", "e1g1", "e2a6",
+                    "e2b5", "e2c4", "e2d1", "e2d3", "e2f1", "e5c4", "e5c6", "e5d3", "e5d7", "e5f7", "e5g4",
+                    "e5g6", "f3d3", "f3e3", "f3f4", "f3f5", "f3f6", "f3g3", "f3g4", "f3h3", "f3h5", "g2g3",
+                    "g2g4", "g2h3", "h1f1", "h1g1",
+                ],
+            ),
+        ];
+
+        for (fen, reference) in cases {
+            let moves = sorted_legal_moves(fen);
+            assert_eq!(moves.len(), reference.len(), "{}: {:?}", fen, moves);
+            assert_eq!(moves, reference, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_has_legal_move() {
+        assert!(BoardState::new().has_legal_move());
+
+        // Scholar's mate
+        let mut mated =
+            BoardState::from_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4").unwrap();
+        assert!(!mated.has_legal_move());
+        assert!(mated.generate_legal_moves().is_empty());
+    }
+
+    #[test]
+    fn test_order_moves() {
+        // The e4 pawn can take the d5 queen or the f5 knight, the c3 knight can take the queen too
+        let mut board = BoardState::from_fen("4k3/8/8/3q1n2/4P3/2N5/8/4K3 w - - 0 1").unwrap();
+        let moves = board.generate_moves();
+
+        let ordered = board.order_moves(moves.clone());
+        assert_eq!(ordered.len(), moves.len());
+        assert!(ordered.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        // Pawn takes queen first, then knight takes queen, then pawn takes knight
+        let best: Vec<ChessMove> = ordered.iter().take(3).map(|scored| scored.chess_move).collect();
+        assert_eq!(
+            best,
+            vec![
+                ChessMove { from: 28, to: 35, promotion: None },
+                ChessMove { from: 18, to: 35, promotion: None },
+                ChessMove { from: 28, to: 37, promotion: None },
+            ]
+        );
+        assert!(ordered[3..].iter().all(|scored| !board.is_capture(&scored.chess_move)));
+
+        // Taking with the king comes after taking the same piece with a pawn
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/3p4/3KP3/8 w - - 0 1").unwrap();
+        let moves = board.generate_moves();
+        let ordered = board.order_moves(moves);
+        assert_eq!(ordered[0].chess_move, ChessMove { from: 12, to: 19, promotion: None });
+        assert_eq!(ordered[1].chess_move, ChessMove { from: 11, to: 19, promotion: None });
+    }
+
+    #[test]
+    fn test_capture_order_key() {
+        // Five white pieces can take the d5 queen or the f5 knight, the e3 knight either of them
+        let mut board = BoardState::from_fen("4k3/8/8/3q1n2/4P3/2N1N2Q/8/3RK3 w - - 0 1").unwrap();
+        let mut captures: Vec<ChessMove> =
+            board.generate_moves().into_iter().filter(|m| board.is_capture(m)).collect();
+        captures.sort_by_key(|m| board.capture_order_key(m));
+
+        let expected: Vec<ChessMove> = ["e4d5", "c3d5", "e3d5", "d1d5", "e4f5", "e3f5", "h3f5"]
+            .into_iter()
+            .map(|text| ChessMove::from_uci(text).unwrap())
+            .collect();
+        assert_eq!(captures, expected);
+
+        // Quiet moves sort after every capture
+        let quiet = ChessMove::from_uci("e1f2").unwrap();
+        assert!(board.capture_order_key(&quiet) > board.capture_order_key(&expected[6]));
+    }
 }
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use crate::board::BoardState;
+use crate::fen::square_name;
+use crate::moves::ChessMove;
+use crate::pieces::{PieceColour, PieceKind};
+
+/// Engine notes attached to a move for analysis output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotation {
+    pub score: Option<i32>, // Centipawns from White's point of view
+    pub comment: Option<String>,
+}
+
+impl BoardState {
+    /// Write a legal move in standard algebraic notation, such as "Nbd2", "exd5" or "O-O+".
+    pub fn san(&mut self, chess_move: &ChessMove) -> String {
+        let piece = self.piece_at(chess_move.from).expect("Piece must exist at 'from'");
+        let capture = self.is_capture(chess_move);
+
+        let mut san = if piece.kind == PieceKind::King && chess_move.from.abs_diff(chess_move.to) == 2 {
+            if chess_move.to > chess_move.from { "O-O" } else { "O-O-O" }.to_string()
+        } else if piece.kind == PieceKind::Pawn {
+            let mut san = String::new();
+            if capture {
+                san.push(file_char(chess_move.from));
+                san.push('x');
+            }
+            san.push_str(&square_name(chess_move.to));
+            if let Some(promotion) = chess_move.promotion {
+                san.push('=');
+                san.push(piece_letter(promotion));
+            }
+            san
+        } else {
+            let mut san = piece_letter(piece.kind).to_string();
+            san.push_str(&self.disambiguation(chess_move, piece.kind));
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(chess_move.to));
+            san
+        };
+
+        if self.move_is_mate(*chess_move) {
+            san.push('#');
+        } else if self.move_gives_check(*chess_move) {
+            san.push('+');
+        }
+        san
+    }
+
+    /// The file, rank or square needed to tell the move apart from other pieces of the same
+    /// kind that can reach the same square.
+    fn disambiguation(&mut self, chess_move: &ChessMove, kind: PieceKind) -> String {
+        let rivals: Vec<usize> = self
+            .generate_legal_moves()
+            .into_iter()
+            .filter(|m| m.to == chess_move.to && m.from != chess_move.from)
+            .filter(|m| self.piece_at(m.from).is_some_and(|piece| piece.kind == kind))
+            .map(|m| m.from)
+            .collect();
+
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|&square| square % 8 != chess_move.from % 8) {
+            file_char(chess_move.from).to_string()
+        } else if rivals.iter().all(|&square| square / 8 != chess_move.from / 8) {
+            ((b'1' + (chess_move.from / 8) as u8) as char).to_string()
+        } else {
+            square_name(chess_move.from)
+        }
+    }
+}
+
+/// Write the movetext of a game played from `start`, with annotations keyed by the index of
+/// the move they follow. Scores are written in pawns, as in `{+0.35}`.
+pub fn export_pgn(start: &BoardState, moves: &[ChessMove], annotations: &HashMap<usize, Annotation>) -> String {
+    let mut board = start.clone();
+    let mut tokens = Vec::new();
+    let mut move_number = 1;
+    let mut after_comment = false;
+
+    for (index, chess_move) in moves.iter().enumerate() {
+        match board.to_move {
+            PieceColour::White => tokens.push(format!("{}.", move_number)),
+            // Black's move needs its number again when something interrupts the move pair
+            PieceColour::Black if index == 0 || after_comment => tokens.push(format!("{}...", move_number)),
+            PieceColour::Black => {}
+        }
+
+        tokens.push(board.san(chess_move));
+        if board.to_move == PieceColour::Black {
+            move_number += 1;
+        }
+        board.make_move(*chess_move);
+
+        after_comment = false;
+        if let Some(annotation) = annotations.get(&index) {
+            let text = annotation_text(annotation);
+            if !text.is_empty() {
+                tokens.push(format!("{{{}}}", text));
+                after_comment = true;
+            }
+        }
+    }
+
+    tokens.push("*".to_string());
+    tokens.join(" ")
+}
+
+/// Comment body for an annotation. PGN comments can't contain a closing brace, so braces in
+/// the comment become parentheses, opening and closing alike to keep them paired.
+fn annotation_text(annotation: &Annotation) -> String {
+    let score = annotation
+        .score
+        .map(|score| format!("{}{:.2}", if score >= 0 { "+" } else { "-" }, score.abs() as f64 / 100.0));
+    let comment = annotation.comment.as_ref().map(|comment| comment.replace('{', "(").replace('}', ")"));
+
+    [score, comment].into_iter().flatten().collect::<Vec<_>>().join(" ")
+}
+
+fn file_char(square: usize) -> char {
+    (b'a' + (square % 8) as u8) as char
+}
+
+fn piece_letter(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::Pawn => 'P',
+        PieceKind::Knight => 'N',
+        PieceKind::Bishop => 'B',
+        PieceKind::Rook => 'R',
+        PieceKind::Queen => 'Q',
+        PieceKind::King => 'K',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uci_moves(moves: &[&str]) -> Vec<ChessMove> {
+        moves.iter().map(|text| ChessMove::from_uci(text).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_san() {
+        let mut board = BoardState::from_fen("r3k3/1P6/8/3pP3/8/2N5/8/R3K1NR w KQq d6 0 1").unwrap();
+
+        assert_eq!(board.san(&ChessMove::from_uci("e5d6").unwrap()), "exd6");
+        assert_eq!(board.san(&ChessMove::from_uci("e1c1").unwrap()), "O-O-O");
+        assert_eq!(board.san(&ChessMove::from_uci("g1e2").unwrap()), "Nge2");
+        assert_eq!(board.san(&ChessMove::from_uci("b7a8q").unwrap()), "bxa8=Q+");
+        assert_eq!(board.san(&ChessMove::from_uci("a1a7").unwrap()), "Ra7");
+    }
+
+    #[test]
+    fn test_export_with_annotation() {
+        let moves = uci_moves(&["e2e4", "e7e5", "g1f3", "b8c6"]);
+        let mut annotations = HashMap::new();
+        annotations.insert(
+            0,
+            Annotation {
+                score: Some(35),
+                comment: Some("best by test".to_string()),
+            },
+        );
+
+        assert_eq!(
+            export_pgn(&BoardState::new(), &moves, &annotations),
+            "1. e4 {+0.35 best by test} 1... e5 2. Nf3 Nc6 *"
+        );
+        assert_eq!(
+            export_pgn(&BoardState::new(), &moves, &HashMap::new()),
+            "1. e4 e5 2. Nf3 Nc6 *"
+        );
+    }
+
+    #[test]
+    fn test_export_mate_and_negative_score() {
+        let moves = uci_moves(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let mut annotations = HashMap::new();
+        annotations.insert(
+            3,
+            Annotation {
+                score: Some(-5),
+                comment: Some("a {braced} note".to_string()),
+            },
+        );
+
+        assert_eq!(
+            export_pgn(&BoardState::new(), &moves, &annotations),
+            "1. f3 e5 2. g4 Qh4# {-0.05 a (braced) note} *"
+        );
+    }
+}
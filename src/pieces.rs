@@ -1,3 +1,11 @@
+use core::ops::Range;
+
+pub const PAWN_VALUE: i32 = 100;
+pub const KNIGHT_VALUE: i32 = 320;
+pub const BISHOP_VALUE: i32 = 330;
+pub const ROOK_VALUE: i32 = 500;
+pub const QUEEN_VALUE: i32 = 900;
+
 // Possible piece colours
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PieceColour {
@@ -13,6 +21,47 @@ impl PieceColour {
             PieceColour::Black => PieceColour::White,
         }
     }
+
+    /// Index of the colour in colour-indexed tables: 0 for White, 1 for Black.
+    pub fn index(self) -> usize {
+        match self {
+            PieceColour::White => 0,
+            PieceColour::Black => 1,
+        }
+    }
+
+    /// The colour with the given table index. Panics if the index is not 0 or 1.
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            0 => PieceColour::White,
+            1 => PieceColour::Black,
+            _ => panic!("Invalid colour index {}", index),
+        }
+    }
+
+    /// Square offset of a single pawn push: up the board for White, down it for Black.
+    pub fn pawn_push_offset(self) -> isize {
+        match self {
+            PieceColour::White => 8,
+            PieceColour::Black => -8,
+        }
+    }
+
+    /// Squares of the rank the colour's pawns start on, where they may push two squares.
+    pub fn starting_rank_range(self) -> Range<usize> {
+        match self {
+            PieceColour::White => 8..16,
+            PieceColour::Black => 48..56,
+        }
+    }
+
+    /// Rank, counted from 0 for the first, on which the colour's pawns promote.
+    pub fn promotion_rank(self) -> usize {
+        match self {
+            PieceColour::White => 7,
+            PieceColour::Black => 0,
+        }
+    }
 }
 
 /// Represents the different kinds of chess pieces (e.g., Pawn, Knight).
@@ -26,9 +75,87 @@ pub enum PieceKind {
     King,
 }
 
+impl PieceKind {
+    /// Every piece kind, from pawn to king.
+    pub const ALL: [PieceKind; 6] = [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+        PieceKind::King,
+    ];
+
+    /// Index of the kind in kind-indexed tables, following the order of `ALL`.
+    pub fn index(self) -> usize {
+        match self {
+            PieceKind::Pawn => 0,
+            PieceKind::Knight => 1,
+            PieceKind::Bishop => 2,
+            PieceKind::Rook => 3,
+            PieceKind::Queen => 4,
+            PieceKind::King => 5,
+        }
+    }
+
+    /// The kind with the given table index. Panics if the index is 6 or more.
+    pub fn from_index(index: usize) -> Self {
+        Self::ALL[index]
+    }
+
+    /// Centipawn value of the piece, with the king counted as zero since it can't be traded.
+    pub fn value(&self) -> i32 {
+        match self {
+            PieceKind::Pawn => PAWN_VALUE,
+            PieceKind::Knight => KNIGHT_VALUE,
+            PieceKind::Bishop => BISHOP_VALUE,
+            PieceKind::Rook => ROOK_VALUE,
+            PieceKind::Queen => QUEEN_VALUE,
+            PieceKind::King => 0,
+        }
+    }
+}
+
 /// Represents a chess piece with its kind and colour.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Piece {
     pub kind: PieceKind,
     pub colour: PieceColour,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piece_values() {
+        assert_eq!(PieceKind::Queen.value(), QUEEN_VALUE);
+
+        let kinds = [PieceKind::Pawn, PieceKind::Knight, PieceKind::Bishop, PieceKind::Rook, PieceKind::Queen];
+        assert!(kinds.windows(2).all(|pair| pair[0].value() < pair[1].value()));
+        assert_eq!(PieceKind::King.value(), 0);
+    }
+
+    #[test]
+    fn test_indices() {
+        assert_eq!(PieceKind::King.index(), 5);
+        assert_eq!(PieceKind::from_index(5), PieceKind::King);
+        assert!(PieceKind::ALL.iter().all(|&kind| PieceKind::from_index(kind.index()) == kind));
+
+        assert_eq!(PieceColour::Black.index(), 1);
+        assert_eq!(PieceColour::from_index(PieceColour::White.index()), PieceColour::White);
+    }
+
+    #[test]
+    fn test_pawn_geometry() {
+        assert_eq!(PieceColour::White.pawn_push_offset(), 8);
+        assert_eq!(PieceColour::Black.pawn_push_offset(), -8);
+
+        // a2-h2 and a7-h7
+        assert_eq!(PieceColour::White.starting_rank_range(), 8..16);
+        assert_eq!(PieceColour::Black.starting_rank_range(), 48..56);
+
+        assert_eq!(PieceColour::White.promotion_rank(), 7);
+        assert_eq!(PieceColour::Black.promotion_rank(), 0);
+    }
+}
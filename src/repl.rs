@@ -0,0 +1,92 @@
+use std::io::{self, BufRead, Write};
+
+use crate::game_logic::Game;
+use crate::moves::ChessMove;
+
+/// Play moves typed in by hand, in UCI ("e2e4") or SAN ("Nf3") form, printing the board after
+/// each one. Runs until `quit`, the end of the input or the end of the game.
+pub fn run(input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    let mut game = Game::new();
+    let mut lines = input.lines();
+
+    loop {
+        write!(out, "{}", game.board())?;
+        if let Some(result) = game.board().game_result(game.history()) {
+            writeln!(out, "Game over: {:?}", result)?;
+            return Ok(());
+        }
+
+        write!(out, "> ")?;
+        out.flush()?;
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let text = line?;
+        let text = text.trim();
+
+        match text {
+            "" => {}
+            "quit" => return Ok(()),
+            _ => match parse_move(&mut game, text) {
+                Some(chess_move) => {
+                    game.play(chess_move).expect("parsed moves are legal");
+                }
+                None => writeln!(out, "Illegal or unrecognised move: {}", text)?,
+            },
+        }
+    }
+}
+
+/// Find the legal move written in UCI or SAN form. Check and mate marks are optional.
+fn parse_move(game: &mut Game, text: &str) -> Option<ChessMove> {
+    let legal = game.legal_moves();
+    if let Some(chess_move) = ChessMove::from_uci(text).filter(|m| legal.contains(m)) {
+        return Some(chess_move);
+    }
+
+    let wanted = text.trim_end_matches(['+', '#']);
+    let mut board = game.board().clone();
+    legal
+        .into_iter()
+        .find(|m| board.san(m).trim_end_matches(['+', '#']) == wanted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_script(script: &str) -> String {
+        let mut out = Vec::new();
+        run(script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_scripted_moves() {
+        let output = run_script("e2e4\nNf6\nquit\n");
+
+        // The board is printed before each prompt, three times in all
+        assert_eq!(output.matches("> ").count(), 3);
+        assert!(!output.contains("Illegal"));
+
+        // The last board shows both moves played
+        let last_board = output.rsplit("> ").nth(1).unwrap();
+        assert!(last_board.contains("6 . . . . . n . ."), "{}", last_board);
+        assert!(last_board.contains("4 . . . . P . . ."), "{}", last_board);
+        assert!(last_board.contains("White to move"), "{}", last_board);
+    }
+
+    #[test]
+    fn test_bad_input_reprompts() {
+        let output = run_script("e2e5\nhello\ne2e4\n");
+
+        assert_eq!(output.matches("Illegal or unrecognised move").count(), 2);
+        assert!(output.contains("Black to move"));
+    }
+
+    #[test]
+    fn test_stops_at_game_end() {
+        let output = run_script("f3\ne5\ng4\nQh4#\ne2e4\n");
+        assert!(output.contains("Game over: Checkmate { winner: Black }"), "{}", output);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::board::BoardState;
+use crate::fen::BACK_RANKS;
+use crate::pieces::{PieceColour, PieceKind};
+
+/// Knights, bishops, rooks and queens each side starts with, beyond which a piece can only
+/// have come from a promotion.
+const STARTING_PIECES: [(PieceKind, u32); 4] =
+    [(PieceKind::Knight, 2), (PieceKind::Bishop, 2), (PieceKind::Rook, 2), (PieceKind::Queen, 1)];
+
+/// Limits on how pieces may be placed, so setups for custom variants can be checked before
+/// they are played. The default enforces standard chess.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Ruleset {
+    /// Kings each side must have, no more and no fewer.
+    pub kings: u32,
+    /// Most pawns a side may have.
+    pub max_pawns: u32,
+    /// Most pieces a side may have, king and pawns included.
+    pub max_pieces: u32,
+    /// Whether pawns may stand on the first and eighth ranks.
+    pub pawns_on_back_ranks: bool,
+    /// Whether knights, bishops, rooks and queens beyond the starting set have to be paid for
+    /// by missing pawns, as promotions.
+    pub promotions_need_pawns: bool,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self {
+            kings: 1,
+            max_pawns: 8,
+            max_pieces: 16,
+            pawns_on_back_ranks: false,
+            promotions_need_pawns: true,
+        }
+    }
+}
+
+/// Ways a position can break a `Ruleset`. Errors about one side name that side.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PositionError {
+    WrongKingCount(PieceColour),
+    TooManyPawns(PieceColour),
+    TooManyPieces(PieceColour),
+    TooManyPromotedPieces(PieceColour), // More extra pieces than missing pawns
+    PawnOnBackRank,
+}
+
+impl BoardState {
+    /// Check the placement of the pieces against the rules, reporting the first one broken.
+    /// Only the pieces are looked at, not whose move it is or whether the position could
+    /// have been reached.
+    pub fn validate(&self, rules: &Ruleset) -> Result<(), PositionError> {
+        for colour in [PieceColour::White, PieceColour::Black] {
+            let count = |kind| self.piece_bitboard(kind, colour).count();
+            let pawns = count(PieceKind::Pawn);
+
+            if count(PieceKind::King) != rules.kings {
+                return Err(PositionError::WrongKingCount(colour));
+            }
+            if pawns > rules.max_pawns {
+                return Err(PositionError::TooManyPawns(colour));
+            }
+            let pieces = PieceKind::ALL.into_iter().map(count).sum::<u32>();
+            if pieces > rules.max_pieces {
+                return Err(PositionError::TooManyPieces(colour));
+            }
+
+            let promoted: u32 = STARTING_PIECES
+                .iter()
+                .map(|&(kind, starting)| count(kind).saturating_sub(starting))
+                .sum();
+            if rules.promotions_need_pawns && promoted > rules.max_pawns.saturating_sub(pawns) {
+                return Err(PositionError::TooManyPromotedPieces(colour));
+            }
+        }
+
+        if !rules.pawns_on_back_ranks && (self.white_pawns.0 | self.black_pawns.0) & BACK_RANKS != 0 {
+            return Err(PositionError::PawnOnBackRank);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::Piece;
+
+    #[test]
+    fn test_standard_positions_are_valid() {
+        let rules = Ruleset::default();
+        assert_eq!(BoardState::new().validate(&rules), Ok(()));
+
+        // Two queens are fine once a pawn has gone to make one of them
+        let promoted = BoardState::from_fen("4k3/8/8/8/8/8/1PPPPPPP/QQ2K3 w - - 0 1").unwrap();
+        assert_eq!(promoted.validate(&rules), Ok(()));
+    }
+
+    #[test]
+    fn test_too_many_pawns() {
+        let nine_pawns = BoardState::from_fen("4k3/8/8/8/8/P7/PPPPPPPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            nine_pawns.validate(&Ruleset::default()),
+            Err(PositionError::TooManyPawns(PieceColour::White))
+        );
+
+        // A variant with an extra pawn allows it
+        let rules = Ruleset { max_pawns: 9, ..Ruleset::default() };
+        assert_eq!(nine_pawns.validate(&rules), Ok(()));
+    }
+
+    #[test]
+    fn test_piece_counts() {
+        let rules = Ruleset::default();
+
+        let three_queens = BoardState::from_fen("4k3/8/8/8/8/8/PPPPPPP1/QQQ1K3 w - - 0 1").unwrap();
+        assert_eq!(three_queens.validate(&rules), Err(PositionError::TooManyPromotedPieces(PieceColour::White)));
+        let free_promotions = Ruleset { promotions_need_pawns: false, ..rules };
+        assert_eq!(three_queens.validate(&free_promotions), Ok(()));
+
+        let crowded = BoardState::from_fen("rnbqkbnr/pppppppp/n7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(crowded.validate(&rules), Err(PositionError::TooManyPieces(PieceColour::Black)));
+
+        let mut no_king = BoardState::new();
+        no_king.remove_piece(60); // e8
+        assert_eq!(no_king.validate(&rules), Err(PositionError::WrongKingCount(PieceColour::Black)));
+    }
+
+    #[test]
+    fn test_pawn_on_back_rank() {
+        // FEN parsing refuses such positions, so the pawn is put there by hand
+        let mut board = BoardState::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        board.add_piece(0, Piece { kind: PieceKind::Pawn, colour: PieceColour::White }); // a1
+
+        assert_eq!(board.validate(&Ruleset::default()), Err(PositionError::PawnOnBackRank));
+        let rules = Ruleset { pawns_on_back_ranks: true, ..Ruleset::default() };
+        assert_eq!(board.validate(&rules), Ok(()));
+    }
+}
@@ -0,0 +1,882 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::attacks::line;
+use crate::board::{BitBoard, BoardState};
+use crate::eval::{evaluate_cached, EvalCache};
+use crate::game_logic::GameResult;
+use crate::history::History;
+use crate::moves::{ChessMove, ScoredMove};
+use crate::pieces::PieceKind;
+#[cfg(feature = "search-trace")]
+use crate::trace::{TraceNode, Tracer};
+
+pub const MATE_SCORE: i32 = 30_000;
+pub const INFINITY: i32 = 32_000;
+
+/// Futility margin per remaining ply of depth.
+const FUTILITY_MARGIN: i32 = 200;
+/// Futility pruning is only applied this close to the leaves.
+const FUTILITY_MAX_DEPTH: u32 = 2;
+
+/// Late move pruning is only applied this close to the leaves.
+const LATE_MOVE_MAX_DEPTH: u32 = 2;
+/// Quiet moves searched per ply of remaining depth before late move pruning skips the rest.
+const LATE_MOVES_PER_PLY: usize = 8;
+
+/// Plies on the halfmove clock before static evals start fading towards a draw.
+const FIFTY_MOVE_FADE_START: u16 = 20;
+/// Plies on the halfmove clock at which the fifty-move rule makes the game a draw.
+const FIFTY_MOVE_LIMIT: u16 = 100;
+
+/// Default for `SearchOptions::max_ply`.
+const MAX_PLY: u32 = 128;
+
+/// Deepest iteration a timed search will start.
+const MAX_DEPTH: u32 = 64;
+/// Nodes searched between checks of the clock.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+/// Number of moves the remaining time is assumed to be spread over.
+const MOVES_TO_GO: u32 = 30;
+/// Time kept back on every move for communication with the GUI.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+/// Switches for the optional search techniques.
+#[derive(Copy, Clone, Debug)]
+pub struct SearchOptions {
+    /// Skip quiet moves at frontier nodes whose static eval is hopelessly below alpha.
+    pub futility_pruning: bool,
+    /// Skip captures that lose material by static exchange evaluation in quiescence.
+    pub see_pruning: bool,
+    /// Near the leaves, search only the first few quiet moves in the ordering and skip the
+    /// rest, unless in check or at a node on the principal variation. Moves that give check
+    /// are never skipped. Unlike a reduction, the skipped moves aren't searched at all.
+    pub late_move_pruning: bool,
+    /// When deepening, search the previous iteration's principal variation first, so the
+    /// best line found so far sets a tight window for everything after it.
+    pub best_move_first: bool,
+    /// Score for a root move that draws on the spot by stalemate or bare kings, from the
+    /// engine's side: below zero to play on for a win, above zero to settle for the draw.
+    /// Draws further down the tree still score zero.
+    pub root_draw_score: i32,
+    /// Search a move that gives check one ply deeper than the others.
+    pub check_extensions: bool,
+    /// Hard limit on how far from the root any line is followed, extensions and quiescence
+    /// included. Nodes this deep are scored by the static eval.
+    pub max_ply: u32,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            futility_pruning: true,
+            see_pruning: true,
+            late_move_pruning: true,
+            best_move_first: true,
+            root_draw_score: 0,
+            check_extensions: false,
+            max_ply: MAX_PLY,
+        }
+    }
+}
+
+/// Time to spend on the next move given the time left on the clock and the increment per move.
+/// Never uses up the whole clock.
+pub fn allocate_time(remaining: Duration, increment: Duration) -> Duration {
+    (remaining / MOVES_TO_GO + increment).min(remaining.saturating_sub(MOVE_OVERHEAD))
+}
+
+/// What the search works out about a node once, before its move loop, rather than per move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NodeContext {
+    pub pins: BitBoard, // Pieces of the side to move pinned to their own king
+    pub checkers: BitBoard, // Enemy pieces giving check to the side to move
+    pub in_check: bool, // Whether the side to move is in check
+}
+
+impl NodeContext {
+    pub fn new(board: &BoardState) -> Self {
+        let checkers = board.checkers(board.to_move);
+        Self {
+            pins: board.pinned_pieces(board.to_move),
+            checkers,
+            in_check: checkers.0 != 0,
+        }
+    }
+
+    /// Check that a pseudo-legal move doesn't leave the mover in check. Out of check, a move by
+    /// a piece other than the king is legal as it stands if the piece is unpinned, or stays on
+    /// the line of its pin, unless it is en passant, which takes a second piece off the board.
+    /// In double check only the king may move. Only the rest are tried on the board.
+    pub fn is_legal(&self, board: &mut BoardState, chess_move: ChessMove) -> bool {
+        let colour = board.to_move;
+        let mover = board.piece_at(chess_move.from).map(|piece| piece.kind);
+        if self.checkers.count() > 1 && mover != Some(PieceKind::King) {
+            return false;
+        }
+        let en_passant = mover == Some(PieceKind::Pawn) && board.en_passant_square == Some(chess_move.to);
+        if !self.in_check && mover != Some(PieceKind::King) && !en_passant {
+            return !self.pins.is_set(chess_move.from)
+                || line(board.king_square(colour), chess_move.from).is_set(chess_move.to);
+        }
+
+        let undo = board.make_move(chess_move);
+        let legal = !board.is_in_check(colour);
+        board.unmake_move(undo);
+        legal
+    }
+}
+
+/// An alpha-beta (negamax) searcher, to a fixed depth or deepening until time runs out or
+/// it is told to stop.
+pub struct Searcher {
+    pub options: SearchOptions,
+    pub nodes: u64, // Nodes visited by the last search
+    pub depth: u32, // Deepest iteration the last search completed
+    pub seldepth: u32, // Deepest ply the last search reached, extensions and quiescence included
+    pub pv: Vec<ChessMove>, // Principal variation found by the last search
+    stop: Arc<AtomicBool>, // Raised from another thread to end a deepening search
+    deadline: Option<Instant>,
+    interruptible: bool, // Whether the current iteration may be cut short
+    stopped: bool, // Set once the search is out of time or stopped, to unwind it
+    pv_table: Vec<Vec<ChessMove>>, // Best line found so far from each ply
+    follow_pv: bool, // Whether the node being searched lies on the previous iteration's PV
+    eval_cache: EvalCache, // Static evals by hash, kept from one search to the next
+    #[cfg(test)]
+    contexts: Option<Vec<(BoardState, NodeContext)>>, // When a test asks: every node searched, with its context
+    #[cfg(feature = "search-trace")]
+    tracer: Tracer,
+}
+
+impl Searcher {
+    pub fn new(options: SearchOptions) -> Self {
+        Self {
+            options,
+            nodes: 0,
+            depth: 0,
+            seldepth: 0,
+            pv: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            deadline: None,
+            interruptible: false,
+            stopped: false,
+            pv_table: Vec::new(),
+            follow_pv: false,
+            eval_cache: EvalCache::default(),
+            #[cfg(test)]
+            contexts: None,
+            #[cfg(feature = "search-trace")]
+            tracer: Tracer::default(),
+        }
+    }
+
+    /// Flag that ends a deepening search once raised. The search leaves it raised; lower it
+    /// again before starting the next one.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Tree of every node visited by the last iteration searched, finished or not.
+    #[cfg(feature = "search-trace")]
+    pub fn trace(&self) -> Option<&TraceNode> {
+        self.tracer.tree()
+    }
+
+    /// Search the position to the given depth, returning the best move and its score
+    /// from the point of view of the side to move.
+    pub fn search(&mut self, board: &mut BoardState, depth: u32) -> (Option<ChessMove>, i32) {
+        self.nodes = 0;
+        self.seldepth = 0;
+        self.deadline = None;
+        self.interruptible = false;
+        self.stopped = false;
+        self.depth = depth;
+        self.pv.clear();
+
+        let result = self.search_root(board, depth);
+        self.pv = self.pv_table[0].clone();
+        result
+    }
+
+    /// Search one depth deeper at a time until the time budget is spent, returning the result
+    /// of the deepest search that finished. The first iteration always runs to completion.
+    pub fn search_timed(&mut self, board: &mut BoardState, budget: Duration) -> (Option<ChessMove>, i32) {
+        self.iterative_deepening(board, Some(Instant::now() + budget), MAX_DEPTH)
+    }
+
+    /// Keep deepening until the stop flag is raised, as for UCI `go infinite` and pondering.
+    pub fn search_until_stopped(&mut self, board: &mut BoardState) -> (Option<ChessMove>, i32) {
+        self.iterative_deepening(board, None, MAX_DEPTH)
+    }
+
+    /// Search one depth deeper at a time up to the given depth, with no time limit. Reaches
+    /// the same depth as `search`, with the earlier iterations guiding the move ordering.
+    pub fn search_deepening(&mut self, board: &mut BoardState, depth: u32) -> (Option<ChessMove>, i32) {
+        self.iterative_deepening(board, None, depth.max(1))
+    }
+
+    fn iterative_deepening(
+        &mut self,
+        board: &mut BoardState,
+        deadline: Option<Instant>,
+        max_depth: u32,
+    ) -> (Option<ChessMove>, i32) {
+        self.nodes = 0;
+        self.seldepth = 0;
+        self.deadline = deadline;
+        self.stopped = false;
+        self.pv.clear();
+
+        let mut result = (None, 0);
+        for depth in 1..=max_depth {
+            self.interruptible = depth > 1;
+            let iteration = self.search_root(board, depth);
+            if self.stopped {
+                break;
+            }
+
+            result = iteration;
+            self.depth = depth;
+            self.pv = self.pv_table[0].clone();
+            let out_of_time = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if out_of_time || self.stop.load(Ordering::Relaxed) || result.0.is_none() {
+                break;
+            }
+        }
+
+        result
+    }
+
+    fn search_root(&mut self, board: &mut BoardState, depth: u32) -> (Option<ChessMove>, i32) {
+        self.clear_pv(0);
+        #[cfg(feature = "search-trace")]
+        self.tracer.enter(None, depth, -INFINITY, INFINITY);
+        let moves = board.generate_legal_moves();
+        if moves.is_empty() {
+            // The game is already over: mated, or stalemated which is a draw
+            let score = if board.side_to_move_in_check() { -MATE_SCORE } else { 0 };
+            #[cfg(feature = "search-trace")]
+            self.tracer.exit(score);
+            return (None, score);
+        }
+
+        let mut best_move = None;
+        let mut alpha = -INFINITY;
+        let pv_move = self.pv_move(0);
+        for ScoredMove { chess_move: m, .. } in pv_move_first(board.order_moves(moves), pv_move) {
+            #[cfg(feature = "search-trace")]
+            self.tracer.enter(Some(m), depth.saturating_sub(1), -INFINITY, -alpha);
+            self.follow_pv = pv_move == Some(m);
+            let pv_node = best_move.is_none();
+            let undo = board.make_move(m);
+            let score = if is_immediate_draw(board) {
+                self.options.root_draw_score
+            } else {
+                -self.alpha_beta(board, depth.saturating_sub(1), -INFINITY, -alpha, 1, pv_node)
+            };
+            board.unmake_move(undo);
+            #[cfg(feature = "search-trace")]
+            self.tracer.exit(-score);
+            if self.stopped {
+                break;
+            }
+            if best_move.is_none() || score > alpha {
+                alpha = score;
+                best_move = Some(m);
+                self.update_pv(0, m);
+            }
+        }
+
+        #[cfg(feature = "search-trace")]
+        self.tracer.exit(alpha);
+        (best_move, alpha)
+    }
+
+    /// Check the clock and the stop flag every so many nodes, latching `stopped` once either
+    /// says the search is over.
+    fn out_of_time(&mut self) -> bool {
+        if self.interruptible && !self.stopped && self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.stopped = self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                || self.stop.load(Ordering::Relaxed);
+        }
+        self.stopped
+    }
+
+    /// The move the previous iteration's principal variation plays at this ply, if the option
+    /// to search it first is on.
+    fn pv_move(&self, ply: usize) -> Option<ChessMove> {
+        if self.options.best_move_first {
+            self.pv.get(ply).copied()
+        } else {
+            None
+        }
+    }
+
+    /// Forget the line from this ply, making room in the table for it and the next ply.
+    fn clear_pv(&mut self, ply: usize) {
+        if self.pv_table.len() < ply + 2 {
+            self.pv_table.resize(ply + 2, Vec::new());
+        }
+        self.pv_table[ply].clear();
+    }
+
+    /// A new best move at this ply: its line is the move followed by the line found below it.
+    fn update_pv(&mut self, ply: usize, chess_move: ChessMove) {
+        let (line, below) = self.pv_table.split_at_mut(ply + 1);
+        line[ply].clear();
+        line[ply].push(chess_move);
+        line[ply].extend_from_slice(&below[0]);
+    }
+
+    fn node_context(&mut self, board: &BoardState) -> NodeContext {
+        let context = NodeContext::new(board);
+        #[cfg(test)]
+        if let Some(contexts) = &mut self.contexts {
+            contexts.push((board.clone(), context));
+        }
+        context
+    }
+
+    /// `pv_node` is set for the first move searched at every node on the line from the root
+    /// that was itself searched first: the line the score at the root comes from.
+    fn alpha_beta(
+        &mut self,
+        board: &mut BoardState,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        ply: u32,
+        pv_node: bool,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+
+        self.clear_pv(ply as usize);
+        if depth == 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+        self.seldepth = self.seldepth.max(ply);
+        if ply >= self.options.max_ply {
+            return self.static_eval(board).max(alpha).min(beta);
+        }
+
+        let context = self.node_context(board);
+        let mut moves = board.generate_moves();
+        moves.retain(|&m| context.is_legal(board, m));
+        if moves.is_empty() {
+            // Checkmate, or stalemate which is only a draw
+            return if context.in_check { -MATE_SCORE + ply as i32 } else { 0 };
+        }
+
+        // Futility pruning: near the leaves, if even a generous margin can't lift the
+        // static eval up to alpha, only captures and promotions are worth searching.
+        let futile = self.options.futility_pruning
+            && depth <= FUTILITY_MAX_DEPTH
+            && !context.in_check
+            && self.static_eval(board) + FUTILITY_MARGIN * depth as i32 <= alpha;
+
+        // Late move pruning: near the leaves, quiet moves this far down the ordering rarely
+        // turn out best, so past a set number of them the rest are skipped. Checks are kept.
+        let prune_late_moves =
+            self.options.late_move_pruning && depth <= LATE_MOVE_MAX_DEPTH && !context.in_check && !pv_node;
+        let late_move_limit = LATE_MOVES_PER_PLY * depth as usize;
+        let mut quiets_searched = 0;
+        let mut first = true;
+
+        let pv_move = if self.follow_pv { self.pv_move(ply as usize) } else { None };
+        for ScoredMove { chess_move: m, .. } in pv_move_first(board.order_moves(moves), pv_move) {
+            let quiet = !board.is_capture(&m) && !m.is_promotion();
+            if quiet && futile {
+                continue;
+            }
+            if quiet && prune_late_moves && quiets_searched >= late_move_limit && !board.move_gives_check(m) {
+                continue;
+            }
+            if quiet {
+                quiets_searched += 1;
+            }
+
+            #[cfg(feature = "search-trace")]
+            self.tracer.enter(Some(m), depth - 1, -beta, -alpha);
+            self.follow_pv = pv_move == Some(m);
+            let child_pv_node = pv_node && first;
+            first = false;
+            let undo = board.make_move(m);
+            let extension = u32::from(self.options.check_extensions && board.side_to_move_in_check());
+            let score = -self.alpha_beta(board, depth - 1 + extension, -beta, -alpha, ply + 1, child_pv_node);
+            board.unmake_move(undo);
+            #[cfg(feature = "search-trace")]
+            self.tracer.exit(-score);
+
+            if score >= beta {
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply as usize, m);
+            }
+        }
+
+        alpha
+    }
+
+    /// Search captures only until the position is quiet, so the static eval isn't taken in
+    /// the middle of an exchange.
+    fn quiescence(&mut self, board: &mut BoardState, mut alpha: i32, beta: i32, ply: u32) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+        self.seldepth = self.seldepth.max(ply);
+
+        // Standing pat: the side to move doesn't have to capture
+        let stand_pat = self.static_eval(board);
+        if ply >= self.options.max_ply {
+            return stand_pat.max(alpha).min(beta);
+        }
+        if stand_pat >= beta {
+            return beta;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        let context = self.node_context(board);
+        let captures = board.generate_captures();
+        for ScoredMove { chess_move: m, .. } in board.order_moves(captures) {
+            if self.options.see_pruning && board.see(&m) < 0 {
+                continue;
+            }
+            if !context.is_legal(board, m) {
+                continue;
+            }
+
+            #[cfg(feature = "search-trace")]
+            self.tracer.enter(Some(m), 0, -beta, -alpha);
+            let undo = board.make_move(m);
+            let score = -self.quiescence(board, -beta, -alpha, ply + 1);
+            board.unmake_move(undo);
+            #[cfg(feature = "search-trace")]
+            self.tracer.exit(-score);
+
+            if score >= beta {
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        alpha
+    }
+
+    /// The static eval of the position, looked up in the eval cache, faded for the halfmove
+    /// clock. The clock isn't part of the hash, so the cache holds the eval before fading.
+    fn static_eval(&mut self, board: &BoardState) -> i32 {
+        let score = evaluate_cached(board, &mut self.eval_cache);
+        fifty_move_fade(score, board.half_move_clock)
+    }
+}
+
+/// A static eval pulled linearly towards zero once the halfmove clock passes
+/// `FIFTY_MOVE_FADE_START`, down to nothing at the fifty-move limit. An advantage that isn't
+/// being turned into pawn moves or captures is worth less and less, so the engine prefers
+/// the moves that reset the clock over shuffling towards a draw.
+fn fifty_move_fade(score: i32, half_move_clock: u16) -> i32 {
+    let clock = half_move_clock.min(FIFTY_MOVE_LIMIT);
+    if clock <= FIFTY_MOVE_FADE_START {
+        return score;
+    }
+    score * i32::from(FIFTY_MOVE_LIMIT - clock) / i32::from(FIFTY_MOVE_LIMIT - FIFTY_MOVE_FADE_START)
+}
+
+/// Move the principal variation's move, when it is among the moves, to the front and leave
+/// the rest in order.
+fn pv_move_first(mut ordered: Vec<ScoredMove>, pv_move: Option<ChessMove>) -> Vec<ScoredMove> {
+    if let Some(index) = ordered.iter().position(|scored| Some(scored.chess_move) == pv_move) {
+        ordered[..=index].rotate_right(1);
+    }
+    ordered
+}
+
+/// Check for a position that is drawn with no more moves played: stalemate or bare kings.
+/// With no history to go on, `game_result` reports no repetition or move-rule draws.
+fn is_immediate_draw(board: &BoardState) -> bool {
+    matches!(
+        board.game_result(&History::new()),
+        Some(GameResult::Stalemate | GameResult::DrawInsufficientMaterial)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{evaluate, ROOK_VALUE};
+
+    // White wins the queen with Rxd5, the black rook on a8 is just ballast
+    const QUEEN_HANGS: &str = "r3k3/8/8/3q4/8/8/PP6/3RK3 w - - 0 1";
+
+    #[test]
+    fn test_search_finds_capture() {
+        let mut board = BoardState::from_fen(QUEEN_HANGS).unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+
+        let (best_move, score) = searcher.search(&mut board, 2);
+
+        assert_eq!(best_move, Some(ChessMove { from: 3, to: 35, promotion: None }));
+        assert!(score > 0);
+    }
+
+    #[test]
+    fn test_futility_pruning_reduces_nodes() {
+        let mut board = BoardState::from_fen(QUEEN_HANGS).unwrap();
+
+        let mut plain = Searcher::new(SearchOptions { futility_pruning: false, ..SearchOptions::default() });
+        let (plain_move, plain_score) = plain.search(&mut board, 3);
+
+        let mut pruned = Searcher::new(SearchOptions { futility_pruning: true, ..SearchOptions::default() });
+        let (pruned_move, pruned_score) = pruned.search(&mut board, 3);
+
+        assert_eq!(pruned_move, Some(ChessMove { from: 3, to: 35, promotion: None }));
+        assert_eq!(pruned_move, plain_move);
+        assert_eq!(pruned_score, plain_score);
+        assert!(
+            pruned.nodes < plain.nodes,
+            "futility pruning searched {} nodes, plain search {}",
+            pruned.nodes,
+            plain.nodes
+        );
+    }
+
+    #[test]
+    fn test_king_and_queen_mate_the_bare_king() {
+        let mut board = BoardState::from_fen("8/8/8/4k3/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+
+        for _ in 0..30 {
+            let (best_move, _) = searcher.search(&mut board, 3);
+            board.make_move(best_move.expect("white always has a move"));
+
+            let (reply, _) = searcher.search(&mut board, 2);
+            match reply {
+                Some(reply) => {
+                    board.make_move(reply);
+                }
+                None => {
+                    assert!(board.is_in_check(board.to_move), "white stalemated the black king");
+                    return;
+                }
+            }
+        }
+        panic!("white failed to mate within 30 moves");
+    }
+
+    #[test]
+    fn test_stalemate_scores_as_draw() {
+        // Black to move has no legal moves and isn't in check
+        let stalemate = "k7/8/1Q6/8/8/8/8/7K b - - 0 1";
+        let mut searcher = Searcher::new(SearchOptions::default());
+
+        let mut board = BoardState::from_fen(stalemate).unwrap();
+        assert_eq!(searcher.alpha_beta(&mut board, 2, -INFINITY, INFINITY, 1, true), 0);
+        assert_eq!(searcher.search(&mut board, 2), (None, 0));
+
+        // In check with nowhere to go is mate instead
+        let mut board = BoardState::from_fen("k7/2K5/8/8/8/8/8/Q7 b - - 0 1").unwrap();
+        assert_eq!(searcher.alpha_beta(&mut board, 2, -INFINITY, INFINITY, 1, true), -MATE_SCORE + 1);
+        assert_eq!(searcher.search(&mut board, 2), (None, -MATE_SCORE));
+    }
+
+    #[test]
+    fn test_see_pruning_in_quiescence() {
+        // Nxa4 wins a loose rook, while the queen can also grab pawns that are defended
+        let fen = "4k3/1p1p1p2/2p1p3/6p1/r2Q3p/2N5/8/4K3 w - - 0 1";
+
+        let mut plain = Searcher::new(SearchOptions { see_pruning: false, ..SearchOptions::default() });
+        let plain_score = plain.quiescence(&mut BoardState::from_fen(fen).unwrap(), -INFINITY, INFINITY, 0);
+
+        let mut pruned = Searcher::new(SearchOptions { see_pruning: true, ..SearchOptions::default() });
+        let pruned_score = pruned.quiescence(&mut BoardState::from_fen(fen).unwrap(), -INFINITY, INFINITY, 0);
+
+        let board = BoardState::from_fen(fen).unwrap();
+        assert!(pruned_score >= evaluate(&board) + ROOK_VALUE / 2);
+        assert_eq!(pruned_score, plain_score);
+        assert!(
+            pruned.nodes < plain.nodes,
+            "SEE pruning searched {} quiescence nodes, plain search {}",
+            pruned.nodes,
+            plain.nodes
+        );
+    }
+
+    #[test]
+    fn test_search_scores_checkmate() {
+        // Back rank mate with Ra8#
+        let mut board = BoardState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+
+        let (best_move, score) = searcher.search(&mut board, 2);
+
+        assert_eq!(best_move, Some(ChessMove { from: 0, to: 56, promotion: None }));
+        assert_eq!(score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn test_allocate_time() {
+        let budget = allocate_time(Duration::from_secs(60), Duration::from_secs(1));
+        assert!(budget >= Duration::from_secs(2) && budget <= Duration::from_secs(4), "{:?}", budget);
+
+        // Nearly flagging, the increment can't be spent ahead of time
+        let budget = allocate_time(Duration::from_millis(200), Duration::from_secs(2));
+        assert!(budget < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_search_timed() {
+        let mut board = BoardState::from_fen(QUEEN_HANGS).unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+
+        let started = Instant::now();
+        let (best_move, score) = searcher.search_timed(&mut board, Duration::from_millis(200));
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(searcher.depth >= 2);
+        assert_eq!(best_move, Some(ChessMove { from: 3, to: 35, promotion: None }));
+        assert!(score > 0);
+
+        // Even with no time at all the first iteration produces a move
+        let (best_move, _) = searcher.search_timed(&mut board, Duration::ZERO);
+        assert!(best_move.is_some());
+        assert_eq!(searcher.depth, 1);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        // Ra8 is mate at once; at depth 3 the line has to start with it
+        let mut board = BoardState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+        searcher.search(&mut board, 3);
+        assert_eq!(searcher.pv, vec![ChessMove { from: 0, to: 56, promotion: None }]);
+
+        // The line is legal move after move
+        let mut board = BoardState::from_fen(QUEEN_HANGS).unwrap();
+        searcher.search(&mut board, 3);
+        assert_eq!(searcher.pv.len(), 3);
+        for &m in &searcher.pv {
+            assert!(board.generate_legal_moves().contains(&m));
+            board.make_move(m);
+        }
+    }
+
+    #[test]
+    fn test_stop_flag_ends_search() {
+        let mut board = BoardState::new();
+        let mut searcher = Searcher::new(SearchOptions::default());
+        let stop = searcher.stop_flag();
+
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let (best_move, _) = searcher.search_until_stopped(&mut board);
+        stopper.join().unwrap();
+
+        assert!(best_move.is_some());
+        assert_eq!(searcher.pv.first(), best_move.as_ref());
+    }
+
+    #[test]
+    fn test_root_draw_score() {
+        // Black is a few pawns up but can't move them; Nc6 stalemates the king on a8
+        let fen = "k7/2K5/5p1p/N2p1p1p/3p1p1p/3P1P1P/8/8 w - - 0 1";
+        let stalemate = ChessMove::from_uci("a5c6").unwrap();
+
+        let mut board = BoardState::from_fen(fen).unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+        let (best_move, score) = searcher.search(&mut board, 2);
+        assert_eq!(best_move, Some(stalemate));
+        assert_eq!(score, 0);
+
+        // Told that a draw is as bad as being two pawns down, White plays on
+        let mut searcher = Searcher::new(SearchOptions { root_draw_score: -200, ..SearchOptions::default() });
+        let (best_move, score) = searcher.search(&mut board, 2);
+        assert_ne!(best_move, Some(stalemate));
+        assert!(score > -200 && score < 0, "{}", score);
+    }
+
+    #[test]
+    fn test_node_context_matches_fresh_pins() {
+        // The c3, e3 and f2 pieces are pinned to the white king, the h5 knight to the black one
+        let mut board = BoardState::from_fen("4r2k/8/8/b6n/8/2N1N1q1/5B2/4K2R w - - 0 1").unwrap();
+        assert_eq!(NodeContext::new(&board).pins.count(), 3);
+
+        let mut searcher = Searcher::new(SearchOptions::default());
+        searcher.contexts = Some(Vec::new());
+        searcher.search(&mut board, 3);
+        let contexts = searcher.contexts.unwrap();
+        assert!(!contexts.is_empty());
+
+        for (node, context) in &contexts {
+            assert_eq!(context.pins, node.pinned_pieces(node.to_move));
+            assert_eq!(context.checkers, node.checkers(node.to_move));
+            assert_eq!(context.in_check, node.side_to_move_in_check());
+
+            // The shortcut keeps exactly the moves the full legality check does
+            let mut node = node.clone();
+            let mut moves = node.generate_moves();
+            moves.retain(|&m| context.is_legal(&mut node, m));
+            assert_eq!(moves, node.generate_legal_moves());
+        }
+    }
+
+    #[test]
+    fn test_node_context_in_double_check() {
+        // Taking the checking rook still leaves the knight's check
+        let mut board = BoardState::from_fen("4k3/8/3N4/8/8/8/8/r3R2K b - - 0 1").unwrap();
+        let context = NodeContext::new(&board);
+        assert_eq!(context.checkers.count(), 2);
+        assert!(!context.is_legal(&mut board, ChessMove::from_uci("a1e1").unwrap()));
+
+        let mut moves = board.generate_moves();
+        moves.retain(|&m| context.is_legal(&mut board, m));
+        assert_eq!(moves, board.generate_legal_moves());
+        assert!(moves.iter().all(|m| m.from == 60));
+    }
+
+    #[cfg(feature = "search-trace")]
+    #[test]
+    fn test_search_trace() {
+        let mut board = BoardState::new();
+        let mut searcher = Searcher::new(SearchOptions::default());
+        let (best_move, score) = searcher.search(&mut board, 2);
+
+        let root = searcher.trace().expect("the search was traced");
+        assert_eq!(root.chess_move, None);
+        assert_eq!((root.depth, root.score), (2, score));
+        assert_eq!(root.children.len(), 20);
+
+        // The first move searched gets the full window and every reply; the best child is
+        // the best move, scored from the opponent's side
+        assert_eq!(root.children[0].children.len(), 20);
+        assert!(root.children.iter().all(|child| child.depth == 1));
+        let best = root.children.iter().find(|child| child.chess_move == best_move).unwrap();
+        assert_eq!(best.score, -score);
+        assert!(root.size() > 1 + 20 + 20);
+        assert!(root.to_string().starts_with("root depth 2"));
+    }
+
+    #[test]
+    fn test_late_move_pruning_reduces_nodes() {
+        let italian = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        for (fen, depth) in [(QUEEN_HANGS, 4), (italian, 3)] {
+            let mut board = BoardState::from_fen(fen).unwrap();
+
+            let mut plain = Searcher::new(SearchOptions { late_move_pruning: false, ..SearchOptions::default() });
+            let (plain_move, _) = plain.search(&mut board, depth);
+
+            let mut pruned = Searcher::new(SearchOptions::default());
+            let (pruned_move, _) = pruned.search(&mut board, depth);
+
+            assert_eq!(pruned_move, plain_move, "{}", fen);
+            assert!(
+                pruned.nodes < plain.nodes,
+                "late move pruning searched {} nodes, plain search {}",
+                pruned.nodes,
+                plain.nodes
+            );
+        }
+    }
+
+    #[test]
+    fn test_late_move_pruning_keeps_checks() {
+        // Rd8 mates, but the moves of the pieces on the first two ranks are generated before it
+        // and use up the quiet moves searched at a node off the principal variation
+        let mut board = BoardState::from_fen("7k/6pp/8/8/8/3R4/PPP2PPP/1N2K1N1 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+        let score = searcher.alpha_beta(&mut board, 2, -INFINITY, INFINITY, 1, false);
+        assert_eq!(score, MATE_SCORE - 2);
+    }
+
+    #[test]
+    fn test_best_move_first_saves_nodes() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+
+        let mut board = BoardState::from_fen(fen).unwrap();
+        let mut plain = Searcher::new(SearchOptions { best_move_first: false, ..SearchOptions::default() });
+        plain.search_deepening(&mut board, 4);
+
+        let mut seeded = Searcher::new(SearchOptions::default());
+        let (best_move, _) = seeded.search_deepening(&mut board, 4);
+
+        assert_eq!(seeded.depth, 4);
+        assert_eq!(seeded.pv.first(), best_move.as_ref());
+        assert!(
+            seeded.nodes < plain.nodes,
+            "seeded search visited {} nodes, plain {}",
+            seeded.nodes,
+            plain.nodes
+        );
+    }
+
+    #[test]
+    fn test_max_ply_guard_with_check_extensions() {
+        // Both queens can keep checking the open kings, so extended lines run on and on
+        let fen = "6k1/8/8/3q4/8/8/3Q4/6K1 w - - 0 1";
+        let options = SearchOptions { check_extensions: true, max_ply: 8, ..SearchOptions::default() };
+
+        let mut board = BoardState::from_fen(fen).unwrap();
+        let mut searcher = Searcher::new(options);
+        let (best_move, _) = searcher.search(&mut board, 5);
+
+        assert!(best_move.is_some());
+        assert_eq!(searcher.seldepth, 8);
+        assert_eq!(board, BoardState::from_fen(fen).unwrap());
+
+        // Without the extensions the same search stays shallower
+        let mut searcher = Searcher::new(SearchOptions { check_extensions: false, ..options });
+        searcher.search(&mut board, 5);
+        assert!(searcher.seldepth < 8, "{}", searcher.seldepth);
+    }
+
+    #[test]
+    fn test_fifty_move_fade() {
+        let mut board = BoardState::from_fen("7k/8/8/8/8/8/P7/R3K3 w - - 0 1").unwrap();
+        let full = evaluate(&board);
+        let mut searcher = Searcher::new(SearchOptions::default());
+        assert_eq!(searcher.static_eval(&board), full);
+
+        // The clock isn't in the hash, so these find the cached eval and fade it afresh
+        board.half_move_clock = 60;
+        assert_eq!(searcher.static_eval(&board), full / 2);
+        board.half_move_clock = 100;
+        assert_eq!(searcher.static_eval(&board), 0);
+        assert_eq!(searcher.eval_cache.hits, 2);
+    }
+
+    #[test]
+    fn test_search_uses_eval_cache() {
+        let mut board = BoardState::from_fen(QUEEN_HANGS).unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+        let (best_move, score) = searcher.search(&mut board, 3);
+        assert!(searcher.eval_cache.hits > 0);
+
+        // Positions met in one search are still cached for the next, which finds the same
+        let hits = searcher.eval_cache.hits;
+        assert_eq!(searcher.search(&mut board, 3), (best_move, score));
+        assert!(searcher.eval_cache.hits > hits);
+    }
+
+    #[test]
+    fn test_pawn_move_resets_stale_clock() {
+        // White is winning but hasn't moved a pawn or captured for 45 moves
+        let mut board = BoardState::from_fen("7k/8/8/8/8/8/P7/R3K3 w - - 90 80").unwrap();
+        let mut searcher = Searcher::new(SearchOptions::default());
+
+        let (best_move, _) = searcher.search(&mut board, 2);
+        let best_move = best_move.unwrap();
+        assert_eq!(best_move.from, 8, "played {} rather than the a-pawn", best_move);
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use crate::moves::ChessMove;
+
+/// One node of a traced search: the move that led to it, the depth and window it was searched
+/// with and the score it returned, all from the point of view of the side to move there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceNode {
+    pub chess_move: Option<ChessMove>, // None for the root
+    pub depth: u32, // Zero in quiescence
+    pub alpha: i32,
+    pub beta: i32,
+    pub score: i32,
+    pub children: Vec<TraceNode>,
+}
+
+/// Builds the tree of a search as it runs. Nodes are opened before their move is searched and
+/// closed with the score it got, so the open nodes always form the path from the root.
+#[derive(Debug, Default)]
+pub struct Tracer {
+    open: Vec<TraceNode>,
+    last: Option<TraceNode>, // Tree of the last search to finish
+}
+
+impl Tracer {
+    pub fn enter(&mut self, chess_move: Option<ChessMove>, depth: u32, alpha: i32, beta: i32) {
+        self.open.push(TraceNode { chess_move, depth, alpha, beta, score: 0, children: Vec::new() });
+    }
+
+    pub fn exit(&mut self, score: i32) {
+        let Some(mut node) = self.open.pop() else {
+            return;
+        };
+        node.score = score;
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.last = Some(node),
+        }
+    }
+
+    /// The tree of the last search, rooted at the starting position.
+    pub fn tree(&self) -> Option<&TraceNode> {
+        self.last.as_ref()
+    }
+}
+
+impl TraceNode {
+    /// Number of nodes in the tree below and including this one.
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().map(TraceNode::size).sum::<usize>()
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter, level: usize) -> fmt::Result {
+        let name = self.chess_move.map_or("root".to_string(), |m| m.to_string());
+        writeln!(
+            f,
+            "{:indent$}{} depth {} [{}, {}] -> {}",
+            "",
+            name,
+            self.depth,
+            self.alpha,
+            self.beta,
+            self.score,
+            indent = 2 * level
+        )?;
+        self.children.iter().try_for_each(|child| child.write_indented(f, level + 1))
+    }
+}
+
+/// One line per node, children indented under their parent.
+impl fmt::Display for TraceNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
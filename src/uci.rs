@@ -0,0 +1,513 @@
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::board::BoardState;
+use crate::fen::START_FEN;
+use crate::moves::{ChessMove, MoveError};
+use crate::pieces::PieceColour;
+use crate::search::{allocate_time, SearchOptions, Searcher, MATE_SCORE};
+
+/// Depth searched by `go` when no depth is given.
+const DEFAULT_DEPTH: u32 = 4;
+/// Scores this close to `MATE_SCORE` are mates, with the distance in plies making up the rest.
+const MATE_THRESHOLD: i32 = MATE_SCORE - 1000;
+
+type SearchResult = (Option<ChessMove>, i32);
+
+/// Something for the command loop to act on: a line from the GUI, or the news that a
+/// background search has returned.
+enum Event {
+    Command(io::Result<String>),
+    InputClosed,
+    SearchFinished(u64), // Numbered as the search that sent it
+}
+
+/// A search running on its own thread for `go ponder` or `go infinite`, which only ends when
+/// the GUI says so, or once `ponderhit` has started its clock and the time runs out.
+struct BackgroundSearch {
+    handle: JoinHandle<(Searcher, SearchResult)>,
+    stop: Arc<AtomicBool>,
+    ponderhit_budget: Duration, // Time to keep searching once the predicted move is played
+    number: u64, // Tells its `SearchFinished` apart from those of searches already stopped
+    returned: bool, // Whether it has sent `SearchFinished`, having returned on its own
+    timer: Option<Timer>, // Started by `ponderhit`; the search then reports when it returns
+}
+
+/// A thread that raises a search's stop flag once its time is up, unless cancelled first.
+struct Timer {
+    cancel: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Timer {
+    fn start(budget: Duration, stop: Arc<AtomicBool>) -> Self {
+        let (cancel, cancelled) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            if cancelled.recv_timeout(budget) == Err(RecvTimeoutError::Timeout) {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        Self { cancel, handle }
+    }
+
+    /// Stop the timer without it touching the flag, so it can't stop a later search.
+    fn cancel(self) {
+        let _ = self.cancel.send(());
+        self.handle.join().expect("timer thread panicked");
+    }
+}
+
+/// Drives the engine over the UCI protocol.
+pub struct Uci {
+    board: BoardState,
+    searcher: Searcher,
+    background: Option<BackgroundSearch>,
+    searches_started: u64, // Background searches so far, for numbering them
+    events: Sender<Event>,
+    pending: Receiver<Event>,
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        let (events, pending) = mpsc::channel();
+        Self {
+            board: BoardState::new(),
+            searcher: Searcher::new(SearchOptions::default()),
+            background: None,
+            searches_started: 0,
+            events,
+            pending,
+        }
+    }
+
+    /// Read commands until `quit` or the end of the input. The input is read on its own thread,
+    /// so a search that runs out of time after `ponderhit` can report while waiting for the
+    /// next command.
+    pub fn run(&mut self, input: impl BufRead + Send + 'static, out: &mut impl Write) -> io::Result<()> {
+        let events = self.events.clone();
+        thread::spawn(move || {
+            for line in input.lines() {
+                if events.send(Event::Command(line)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(Event::InputClosed);
+        });
+
+        while let Ok(event) = self.pending.recv() {
+            if !self.handle_event(event, out)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Act on one event of the command loop. Returns false once the engine should quit.
+    fn handle_event(&mut self, event: Event, out: &mut impl Write) -> io::Result<bool> {
+        match event {
+            Event::Command(line) => self.handle_command(&line?, out),
+            Event::InputClosed => Ok(false),
+            Event::SearchFinished(number) => {
+                self.search_finished(number, out)?;
+                out.flush()?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Handle a single command, writing any reply to `out`. Returns false once the engine should quit.
+    pub fn handle_command(&mut self, line: &str, out: &mut impl Write) -> io::Result<bool> {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                writeln!(out, "id name jurgio_engine")?;
+                writeln!(out, "id author Chaaronn")?;
+                writeln!(out, "option name Ponder type check default false")?;
+                writeln!(out, "uciok")?;
+            }
+            Some("isready") => writeln!(out, "readyok")?,
+            Some("ucinewgame") => {
+                self.finish_background();
+                self.board = BoardState::new();
+            }
+            Some("position") => {
+                self.finish_background();
+                self.position(tokens.collect());
+            }
+            Some("go") => {
+                self.finish_background();
+                self.go(tokens.collect(), out)?;
+            }
+            Some("ponderhit") => self.ponderhit(out)?,
+            Some("stop") => {
+                if let Some(result) = self.finish_background() {
+                    self.report(result, out)?;
+                }
+            }
+            Some("quit") => {
+                self.finish_background();
+                return Ok(false);
+            }
+            Some(command) => tracing::warn!("Unknown UCI command: {}", command),
+            None => {}
+        }
+        out.flush()?;
+        Ok(true)
+    }
+
+    /// Set up the position from `startpos` or `fen <fields>`, then play any `moves` after it.
+    fn position(&mut self, tokens: Vec<&str>) {
+        let moves_at = tokens.iter().position(|&token| token == "moves").unwrap_or(tokens.len());
+        let fen = match tokens.first() {
+            Some(&"startpos") => START_FEN.to_string(),
+            Some(&"fen") => tokens[1..moves_at].join(" "),
+            _ => {
+                tracing::warn!("Malformed position command: {:?}", tokens);
+                return;
+            }
+        };
+
+        self.board = match BoardState::from_fen(&fen) {
+            Ok(board) => board,
+            Err(err) => {
+                tracing::warn!("Invalid FEN {:?}: {:?}", fen, err);
+                return;
+            }
+        };
+
+        // Play up to the first move that can't be read or played
+        let texts = tokens.get(moves_at + 1..).unwrap_or_default();
+        let moves: Vec<ChessMove> = texts.iter().map_while(|text| ChessMove::from_uci(text)).collect();
+        if let Err(MoveError::IllegalMoveAt(index)) = self.board.make_moves(&moves) {
+            tracing::warn!("Illegal move in position command: {}", texts[index]);
+        } else if let Some(text) = texts.get(moves.len()) {
+            tracing::warn!("Unreadable move in position command: {}", text);
+        }
+    }
+
+    /// Search the current position and report the result. A given depth is searched in full;
+    /// otherwise the time is budgeted from the clock of the side to move when there is one.
+    /// `go ponder` and `go infinite` search in the background until `ponderhit` or `stop`.
+    fn go(&mut self, tokens: Vec<&str>, out: &mut impl Write) -> io::Result<()> {
+        let (time_name, increment_name) = match self.board.to_move {
+            PieceColour::White => ("wtime", "winc"),
+            PieceColour::Black => ("btime", "binc"),
+        };
+        let budget = go_value(&tokens, time_name).map(|remaining| {
+            let increment = go_value(&tokens, increment_name).unwrap_or(0);
+            allocate_time(Duration::from_millis(remaining), Duration::from_millis(increment))
+        });
+
+        if tokens.contains(&"ponder") || tokens.contains(&"infinite") {
+            self.start_background(budget.unwrap_or(Duration::ZERO));
+            return Ok(());
+        }
+
+        let result = match (go_value(&tokens, "depth"), budget) {
+            (None, Some(budget)) => self.searcher.search_timed(&mut self.board, budget),
+            (depth, _) => {
+                let depth = depth.map_or(DEFAULT_DEPTH, |depth| depth as u32);
+                self.searcher.search(&mut self.board, depth)
+            }
+        };
+        self.report(result, out)
+    }
+
+    /// Hand the searcher to a thread that keeps deepening until told to stop.
+    fn start_background(&mut self, ponderhit_budget: Duration) {
+        let mut searcher = mem::replace(&mut self.searcher, Searcher::new(SearchOptions::default()));
+        let mut board = self.board.clone();
+        let stop = searcher.stop_flag();
+        stop.store(false, Ordering::Relaxed);
+
+        self.searches_started += 1;
+        let number = self.searches_started;
+        let events = self.events.clone();
+        let handle = thread::spawn(move || {
+            let result = searcher.search_until_stopped(&mut board);
+            let _ = events.send(Event::SearchFinished(number));
+            (searcher, result)
+        });
+        self.background = Some(BackgroundSearch {
+            handle,
+            stop,
+            ponderhit_budget,
+            number,
+            returned: false,
+            timer: None,
+        });
+    }
+
+    /// Stop any background search and take the searcher back, returning what it found.
+    fn finish_background(&mut self) -> Option<SearchResult> {
+        let background = self.background.take()?;
+        background.stop.store(true, Ordering::Relaxed);
+        let (searcher, result) = background.handle.join().expect("search thread panicked");
+        if let Some(timer) = background.timer {
+            timer.cancel();
+        }
+        self.searcher = searcher;
+        Some(result)
+    }
+
+    /// The opponent played the move we pondered on, so the background search becomes the real
+    /// one: it carries on for the time budgeted by `go ponder` and reports its move when it
+    /// returns. Until then commands are read as usual, so `stop` still ends it at once.
+    fn ponderhit(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let Some(background) = &mut self.background else {
+            tracing::warn!("ponderhit without a ponder search");
+            return Ok(());
+        };
+
+        // A search that already returned won't send word again
+        if background.returned {
+            if let Some(result) = self.finish_background() {
+                self.report(result, out)?;
+            }
+        } else if background.timer.is_none() {
+            background.timer = Some(Timer::start(background.ponderhit_budget, Arc::clone(&background.stop)));
+        }
+        Ok(())
+    }
+
+    /// The numbered background search has returned. After `ponderhit` it reports its move;
+    /// before, as for `go infinite`, the move waits for `stop` or `ponderhit`.
+    fn search_finished(&mut self, number: u64, out: &mut impl Write) -> io::Result<()> {
+        let Some(background) = self.background.as_mut().filter(|background| background.number == number) else {
+            return Ok(()); // Already stopped and reported
+        };
+        if background.timer.is_none() {
+            background.returned = true;
+        } else if let Some(result) = self.finish_background() {
+            self.report(result, out)?;
+        }
+        Ok(())
+    }
+
+    /// Write the outcome of a search, suggesting the reply from the principal variation for
+    /// the GUI to ponder on.
+    fn report(&self, (best_move, score): SearchResult, out: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "info depth {} seldepth {} score {} nodes {} pv {}",
+            self.searcher.depth,
+            self.searcher.seldepth,
+            format_score(score),
+            self.searcher.nodes,
+            self.searcher.pv.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ")
+        )?;
+
+        let best_move = best_move.unwrap_or(ChessMove::null());
+        match self.searcher.pv.get(1) {
+            Some(reply) if self.searcher.pv.first() == Some(&best_move) => {
+                writeln!(out, "bestmove {} ponder {}", best_move, reply)
+            }
+            _ => writeln!(out, "bestmove {}", best_move),
+        }
+    }
+}
+
+/// The number following a named parameter of `go`, such as `depth 6` or `wtime 60000`.
+fn go_value(tokens: &[&str], name: &str) -> Option<u64> {
+    let at = tokens.iter().position(|&token| token == name)?;
+    tokens.get(at + 1)?.parse().ok()
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Convert a search score to UCI: `mate N` in moves for forced mates, negative when the side to
+/// move is getting mated, otherwise `cp N` in centipawns (draws included, as `cp 0`).
+pub fn format_score(score: i32) -> String {
+    if score >= MATE_THRESHOLD {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_THRESHOLD {
+        format!("mate -{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn run_commands(uci: &mut Uci, commands: &[&str]) -> String {
+        let mut out = Vec::new();
+        for command in commands {
+            uci.handle_command(command, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_format_score() {
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(0), "cp 0");
+        assert_eq!(format_score(MATE_SCORE - 1), "mate 1");
+        assert_eq!(format_score(MATE_SCORE - 3), "mate 2");
+        assert_eq!(format_score(-(MATE_SCORE - 2)), "mate -1");
+    }
+
+    #[test]
+    fn test_go_reports_mate_in_two() {
+        // 1.Rb7 Kg8 2.Ra8# or 1.Ra7 Kg8 2.Rb8#, there is no mate in one
+        let mut uci = Uci::new();
+        let output = run_commands(&mut uci, &["position fen 7k/8/8/8/8/8/R7/1R5K w - - 0 1", "go depth 4"]);
+
+        assert!(output.contains("score mate 2"), "{}", output);
+        assert!(
+            output.contains("bestmove b1b7") || output.contains("bestmove a2a7"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_position_with_moves() {
+        let mut uci = Uci::new();
+        let output = run_commands(&mut uci, &["uci", "isready", "position startpos moves e2e4 e7e5"]);
+
+        assert!(output.ends_with("uciok\nreadyok\n"));
+        let expected =
+            BoardState::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").unwrap();
+        assert_eq!(uci.board.all_pieces, expected.all_pieces);
+        assert_eq!(uci.board.white_pawns, expected.white_pawns);
+        assert_eq!(uci.board.to_move, PieceColour::White);
+    }
+
+    #[test]
+    fn test_position_moves_set_en_passant() {
+        let mut uci = Uci::new();
+        run_commands(&mut uci, &["position startpos moves e2e4 a7a6 e4e5 d7d5"]);
+
+        let expected =
+            BoardState::from_fen("rnbqkbnr/1pp1pppp/p7/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3").unwrap();
+        assert_eq!(uci.board.en_passant_square, Some(43));
+        assert_eq!(uci.board.hash(), expected.hash());
+        assert!(uci.board.generate_legal_moves().contains(&ChessMove::from_uci("e5d6").unwrap()));
+
+        // Taking en passant clears the square and the captured pawn
+        run_commands(&mut uci, &["position startpos moves e2e4 a7a6 e4e5 d7d5 e5d6"]);
+        assert_eq!(uci.board.en_passant_square, None);
+        assert!(uci.board.is_empty(35));
+        assert!(uci.board.white_pawns.is_set(43));
+    }
+
+    #[test]
+    fn test_go_with_clock() {
+        let mut uci = Uci::new();
+        let output = run_commands(
+            &mut uci,
+            &["position startpos moves e2e4", "go wtime 1000 btime 300 winc 0 binc 0"],
+        );
+
+        assert!(output.contains("bestmove "), "{}", output);
+        assert!(!output.contains("bestmove 0000"), "{}", output);
+        assert_eq!(go_value(&["wtime", "60000", "winc", "1000"], "winc"), Some(1000));
+        assert_eq!(go_value(&["depth"], "depth"), None);
+    }
+
+    #[test]
+    fn test_ponder_then_ponderhit() {
+        let mut uci = Uci::new();
+        let output = run_commands(
+            &mut uci,
+            &[
+                "position startpos moves e2e4 e7e5",
+                "go ponder wtime 1000 btime 1000",
+                "ponderhit",
+            ],
+        );
+        assert!(!output.contains("bestmove "), "{}", output);
+
+        // The search reports by itself once the time budgeted from the clock is spent
+        let mut out = Vec::new();
+        while uci.background.is_some() {
+            let event = uci.pending.recv_timeout(Duration::from_secs(5)).expect("the search never finished");
+            uci.handle_event(event, &mut out).unwrap();
+        }
+        let output = String::from_utf8(out).unwrap();
+        assert_eq!(output.matches("bestmove ").count(), 1, "{}", output);
+        assert!(!output.contains("bestmove 0000"), "{}", output);
+    }
+
+    #[test]
+    fn test_stop_after_ponderhit() {
+        // A minute on the clock budgets two seconds after ponderhit, but stop cuts it short
+        let mut uci = Uci::new();
+        let started = Instant::now();
+        let output = run_commands(
+            &mut uci,
+            &["position startpos moves e2e4", "go ponder wtime 60000 btime 60000", "ponderhit", "stop"],
+        );
+
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        assert_eq!(output.matches("bestmove ").count(), 1, "{}", output);
+        assert!(uci.background.is_none());
+    }
+
+    #[test]
+    fn test_run_reports_after_ponderhit() {
+        // The input ends with quit only after the search had time to report
+        let mut uci = Uci::new();
+        let (lines, input) = mpsc::channel::<&str>();
+        let reader = io::BufReader::new(ChannelReader(input, Vec::new()));
+        lines.send("go ponder wtime 1000 btime 1000\nponderhit\n").unwrap();
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            lines.send("quit\n").unwrap();
+        });
+        let mut out = Vec::new();
+        uci.run(reader, &mut out).unwrap();
+        writer.join().unwrap();
+
+        let output = String::from_utf8(out).unwrap();
+        assert_eq!(output.matches("bestmove ").count(), 1, "{}", output);
+    }
+
+    /// Input that arrives in chunks over a channel, as from a GUI taking its time.
+    struct ChannelReader(Receiver<&'static str>, Vec<u8>);
+
+    impl io::Read for ChannelReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.1.is_empty() {
+                match self.0.recv() {
+                    Ok(chunk) => self.1.extend_from_slice(chunk.as_bytes()),
+                    Err(_) => return Ok(0),
+                }
+            }
+            let n = buf.len().min(self.1.len());
+            buf[..n].copy_from_slice(&self.1[..n]);
+            self.1.drain(..n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_go_infinite_then_stop() {
+        let mut uci = Uci::new();
+        let mut out = Vec::new();
+        uci.handle_command("go infinite", &mut out).unwrap();
+        assert!(out.is_empty());
+
+        std::thread::sleep(Duration::from_millis(50));
+        uci.handle_command("stop", &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("bestmove "), "{}", output);
+
+        // The searcher is back and usable for the next search
+        let output = run_commands(&mut uci, &["go depth 2"]);
+        assert!(output.contains("info depth 2 seldepth "), "{}", output);
+    }
+}
@@ -0,0 +1,43 @@
+// The binary needs std, so without it there is nothing to run
+#![cfg(feature = "std")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Run the engine binary with `play`, feeding it the script on stdin, and return what it prints.
+fn play(script: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jurgio_engine"))
+        .arg("play")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("engine binary should start");
+
+    child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_play_prints_the_board_after_each_move() {
+    let output = play("e2e4\nNf6\nquit\n");
+
+    // A board and a prompt for the start and after each move, and no UCI chatter
+    assert_eq!(output.matches("> ").count(), 3);
+    assert!(!output.contains("Illegal"));
+    assert!(!output.contains("uciok"));
+
+    let last_board = output.rsplit("> ").nth(1).unwrap();
+    assert!(last_board.contains("6 . . . . . n . ."), "{}", last_board);
+    assert!(last_board.contains("4 . . . . P . . ."), "{}", last_board);
+    assert!(last_board.contains("White to move"), "{}", last_board);
+}
+
+#[test]
+fn test_play_ends_with_the_input() {
+    // No quit: the end of stdin finishes the game loop and the process exits cleanly
+    let output = play("f3\ne5\ng4\nQh4#\n");
+    assert!(output.contains("Game over: Checkmate { winner: Black }"), "{}", output);
+}